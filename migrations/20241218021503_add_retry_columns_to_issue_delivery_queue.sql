@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE issues_delivery_queue ADD COLUMN n_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE issues_delivery_queue ADD COLUMN last_error TEXT NULL;
+ALTER TABLE issues_delivery_queue ADD COLUMN next_attempt_at timestamptz NOT NULL DEFAULT now();
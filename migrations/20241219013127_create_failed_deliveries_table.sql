@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE TYPE delivery_attempt AS (
+    attempted_at timestamptz,
+    error TEXT
+);
+
+ALTER TABLE issues_delivery_queue ADD COLUMN attempts delivery_attempt[] NOT NULL DEFAULT '{}';
+
+CREATE TABLE
+    failed_deliveries (
+        newsletter_issues_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issues_id),
+        subscriber_email TEXT NOT NULL,
+        n_attempts INTEGER NOT NULL,
+        attempts delivery_attempt[] NOT NULL,
+        last_error TEXT NOT NULL,
+        failed_at timestamptz NOT NULL,
+        PRIMARY KEY (newsletter_issues_id, subscriber_email)
+    )
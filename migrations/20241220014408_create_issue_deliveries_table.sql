@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE
+    issue_deliveries (
+        newsletter_issues_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issues_id),
+        subscriber_email TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        n_attempts INTEGER NOT NULL,
+        delivered_at timestamptz NOT NULL,
+        PRIMARY KEY (newsletter_issues_id, subscriber_email)
+    )
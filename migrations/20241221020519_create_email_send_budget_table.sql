@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE
+    email_send_budget (
+        day date NOT NULL,
+        n_sent INTEGER NOT NULL,
+        PRIMARY KEY (day)
+    )
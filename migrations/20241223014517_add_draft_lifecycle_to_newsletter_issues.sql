@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Issues now go through draft -> scheduled -> sending -> sent.
+BEGIN;
+    UPDATE newsletter_issues i
+        SET status = CASE
+            WHEN EXISTS (
+                SELECT 1 FROM issues_delivery_queue q
+                WHERE q.newsletter_issues_id = i.newsletter_issues_id
+            ) THEN 'sending'
+            ELSE 'sent'
+        END
+        WHERE status = 'published';
+    ALTER TABLE newsletter_issues ALTER COLUMN status SET DEFAULT 'draft';
+    ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+COMMIT;
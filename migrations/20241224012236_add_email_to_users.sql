@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Where test copies of newsletter issues are sent.
+ALTER TABLE users ADD COLUMN email TEXT NULL;
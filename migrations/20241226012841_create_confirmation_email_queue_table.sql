@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE
+    confirmation_email_queue (
+        subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+        n_attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_at timestamptz NOT NULL DEFAULT now(),
+        PRIMARY KEY (subscriber_id)
+    )
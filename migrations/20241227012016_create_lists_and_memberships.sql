@@ -0,0 +1,50 @@
+-- Add migration script here
+-- Subscribers can now be on several lists. Everybody who was on the single
+-- list so far becomes a member of the default one.
+BEGIN;
+    CREATE TABLE
+        lists (
+            list_id uuid NOT NULL,
+            name TEXT NOT NULL UNIQUE,
+            is_default BOOLEAN NOT NULL DEFAULT false,
+            created_at timestamptz NOT NULL DEFAULT now(),
+            PRIMARY KEY (list_id)
+        );
+    CREATE UNIQUE INDEX lists_single_default ON lists (is_default) WHERE is_default;
+    INSERT INTO lists (list_id, name, is_default) VALUES (gen_random_uuid(), 'Newsletter', true);
+
+    CREATE TABLE
+        list_memberships (
+            subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+            list_id uuid NOT NULL REFERENCES lists (list_id),
+            status TEXT NOT NULL,
+            subscribed_at timestamptz NOT NULL DEFAULT now(),
+            PRIMARY KEY (subscriber_id, list_id)
+        );
+    INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+    SELECT s.id, l.list_id, s.status, s.subscribed_at
+    FROM subscriptions s, lists l;
+
+    CREATE TABLE
+        newsletter_issue_lists (
+            newsletter_issues_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issues_id) ON DELETE CASCADE,
+            list_id uuid NOT NULL REFERENCES lists (list_id),
+            PRIMARY KEY (newsletter_issues_id, list_id)
+        );
+    INSERT INTO newsletter_issue_lists (newsletter_issues_id, list_id)
+    SELECT i.newsletter_issues_id, l.list_id
+    FROM newsletter_issues i, lists l
+    WHERE i.status <> 'draft';
+
+    -- Confirmation is tracked per list, so tokens and queued confirmation
+    -- emails now name the list they confirm.
+    ALTER TABLE subscription_tokens ADD COLUMN list_id uuid REFERENCES lists (list_id);
+    UPDATE subscription_tokens SET list_id = (SELECT list_id FROM lists);
+    ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+    ALTER TABLE confirmation_email_queue ADD COLUMN list_id uuid REFERENCES lists (list_id);
+    UPDATE confirmation_email_queue SET list_id = (SELECT list_id FROM lists);
+    ALTER TABLE confirmation_email_queue ALTER COLUMN list_id SET NOT NULL;
+    ALTER TABLE confirmation_email_queue DROP CONSTRAINT confirmation_email_queue_pkey;
+    ALTER TABLE confirmation_email_queue ADD PRIMARY KEY (subscriber_id, list_id);
+COMMIT;
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE subscriptions
+    ADD COLUMN email_format TEXT NOT NULL DEFAULT 'html'
+    CHECK (email_format IN ('html', 'plain_text'));
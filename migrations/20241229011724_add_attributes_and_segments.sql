@@ -0,0 +1,18 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE subscriptions
+        ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}'::jsonb;
+    CREATE INDEX subscriptions_attributes_idx ON subscriptions USING GIN (attributes);
+    -- A segment matches the subscribers whose attributes contain all of
+    -- `attributes` and who signed up within the optional bounds.
+    CREATE TABLE segments(
+        segment_id uuid PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        attributes JSONB NOT NULL DEFAULT '{}'::jsonb,
+        subscribed_from timestamptz,
+        subscribed_before timestamptz,
+        created_at timestamptz NOT NULL DEFAULT now()
+    );
+    ALTER TABLE newsletter_issues
+        ADD COLUMN segment_id uuid REFERENCES segments (segment_id);
+COMMIT;
@@ -0,0 +1,30 @@
+-- Add migration script here
+-- Layouts are edited by adding versions; issues point at the version they
+-- were published with, so editing a layout never changes past issues.
+BEGIN;
+    CREATE TABLE
+        layouts (
+            layout_id uuid NOT NULL,
+            name TEXT NOT NULL UNIQUE,
+            is_default BOOLEAN NOT NULL DEFAULT false,
+            created_at timestamptz NOT NULL DEFAULT now(),
+            PRIMARY KEY (layout_id)
+        );
+    CREATE UNIQUE INDEX layouts_single_default ON layouts (is_default) WHERE is_default;
+
+    CREATE TABLE
+        layout_versions (
+            layout_version_id uuid NOT NULL,
+            layout_id uuid NOT NULL REFERENCES layouts (layout_id),
+            version INT NOT NULL,
+            header TEXT NOT NULL,
+            footer TEXT NOT NULL,
+            postal_address TEXT NOT NULL,
+            created_at timestamptz NOT NULL DEFAULT now(),
+            PRIMARY KEY (layout_version_id),
+            UNIQUE (layout_id, version)
+        );
+
+    ALTER TABLE newsletter_issues
+        ADD COLUMN layout_version_id uuid REFERENCES layout_versions (layout_version_id);
+COMMIT;
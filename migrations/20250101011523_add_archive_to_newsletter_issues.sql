@@ -0,0 +1,36 @@
+-- Add migration script here
+-- Slugs are given out when an issue is published; issues published before
+-- the archive existed get theirs here, the same way: a title used before
+-- gets a numeric suffix.
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN slug TEXT UNIQUE;
+    ALTER TABLE newsletter_issues ADD COLUMN hidden_from_archive BOOLEAN NOT NULL DEFAULT false;
+    DO $$
+    DECLARE
+        issue RECORD;
+        base TEXT;
+        candidate TEXT;
+        n INT;
+    BEGIN
+        FOR issue IN
+            SELECT newsletter_issues_id, title
+            FROM newsletter_issues
+            WHERE published_at IS NOT NULL
+            ORDER BY published_at, newsletter_issues_id
+        LOOP
+            base := COALESCE(
+                NULLIF(TRIM(BOTH '-' FROM REGEXP_REPLACE(LOWER(issue.title), '[^a-z0-9]+', '-', 'g')), ''),
+                'issue'
+            );
+            candidate := base;
+            n := 1;
+            WHILE EXISTS (SELECT 1 FROM newsletter_issues WHERE slug = candidate) LOOP
+                n := n + 1;
+                candidate := base || '-' || n;
+            END LOOP;
+            UPDATE newsletter_issues SET slug = candidate
+            WHERE newsletter_issues_id = issue.newsletter_issues_id;
+        END LOOP;
+    END
+    $$;
+COMMIT;
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Confirmation emails now share their queue with the other emails sent to a
+-- single subscriber, told apart by `kind`, so that the API never sends
+-- email while answering a sign-up.
+BEGIN;
+    ALTER TABLE confirmation_email_queue RENAME TO subscriber_email_queue;
+    ALTER TABLE subscriber_email_queue ADD COLUMN kind TEXT NOT NULL DEFAULT 'confirmation';
+    ALTER TABLE subscriber_email_queue ALTER COLUMN kind DROP DEFAULT;
+    ALTER TABLE subscriber_email_queue DROP CONSTRAINT confirmation_email_queue_pkey;
+    ALTER TABLE subscriber_email_queue ADD PRIMARY KEY (subscriber_id, list_id, kind);
+
+    -- When a repeat sign-up last queued an "already subscribed" email.
+    ALTER TABLE subscriptions ADD COLUMN last_notified_at timestamptz NULL;
+COMMIT;
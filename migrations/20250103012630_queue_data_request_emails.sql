@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Data request emails go through the subscriber email queue as well. They
+-- are not about a list, so `list_id` becomes optional.
+BEGIN;
+    ALTER TABLE subscriber_email_queue DROP CONSTRAINT subscriber_email_queue_pkey;
+    ALTER TABLE subscriber_email_queue ALTER COLUMN list_id DROP NOT NULL;
+    ALTER TABLE subscriber_email_queue
+        ADD CONSTRAINT subscriber_email_queue_unique
+        UNIQUE NULLS NOT DISTINCT (subscriber_id, list_id, kind);
+
+    -- When the subscriber last asked for links to their data.
+    ALTER TABLE subscriptions ADD COLUMN data_requested_at timestamptz NULL;
+COMMIT;
@@ -0,0 +1,12 @@
+-- Add migration script here
+-- A single row holding the messages sent in the current one-second window,
+-- shared by every worker process.
+CREATE TABLE
+    email_send_rate (
+        id BOOLEAN NOT NULL DEFAULT TRUE CHECK (id),
+        window_start timestamptz NOT NULL,
+        n_sent INTEGER NOT NULL,
+        PRIMARY KEY (id)
+    );
+
+INSERT INTO email_send_rate (window_start, n_sent) VALUES (to_timestamp(0), 0);
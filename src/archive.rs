@@ -0,0 +1,90 @@
+use sqlx::{Acquire, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Turns a title into the path segment of its archive page: lowercase ASCII
+/// letters and digits, with every other run of characters replaced by a
+/// single hyphen.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "issue".into()
+    } else {
+        slug.into()
+    }
+}
+
+/// Gives a freshly published issue its archive slug. Slugs are never changed
+/// afterwards, so links to the archive keep working; a title that has been
+/// used before gets a numeric suffix.
+#[tracing::instrument(skip(transaction))]
+pub async fn assign_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let title = sqlx::query!(
+        r#"
+        SELECT title FROM newsletter_issues WHERE newsletter_issues_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .title;
+    let base = slugify(&title);
+    let mut n = 1;
+    loop {
+        let slug = if n == 1 {
+            base.clone()
+        } else {
+            format!("{}-{}", base, n)
+        };
+        // The unique index is the only reliable check: another issue with
+        // the same title may be getting its slug in a concurrent transaction.
+        // A savepoint keeps the enclosing transaction usable after a clash.
+        let mut savepoint = transaction.begin().await?;
+        let result = sqlx::query!(
+            r#"
+            UPDATE newsletter_issues SET slug = $2 WHERE newsletter_issues_id = $1
+            "#,
+            issue_id,
+            slug
+        )
+        .execute(&mut savepoint)
+        .await;
+        match result {
+            Ok(_) => return savepoint.commit().await,
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+                savepoint.rollback().await?;
+                n += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+const UNIQUE_VIOLATION: &str = "23505";
+
+#[cfg(test)]
+mod tests {
+    use super::slugify;
+
+    #[test]
+    fn titles_are_lowercased_and_hyphenated() {
+        assert_eq!(slugify("Release notes: v2.0!"), "release-notes-v2-0");
+        assert_eq!(slugify("  -- Hello,   World --  "), "hello-world");
+    }
+
+    #[test]
+    fn titles_without_letters_or_digits_get_a_placeholder() {
+        assert_eq!(slugify("¿¡!?"), "issue");
+        assert_eq!(slugify(""), "issue");
+    }
+}
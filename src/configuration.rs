@@ -5,13 +5,18 @@ use sqlx::{
     ConnectOptions,
 };
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    domain::SubscriberEmail,
+    email_client::{EmailClient, FileTransport, PostmarkTransport, SmtpTransport},
+    rate_limiter::RateLimiter,
+};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub delivery: DeliverySettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -63,26 +68,85 @@ pub struct ApplicationSettings {
 
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// How long in-flight requests and deliveries may take to finish on shutdown.
+    pub shutdown_grace_period_seconds: u64,
+    /// How long a confirmation link stays valid; pending subscribers whose
+    /// links have all expired are purged.
+    pub subscription_token_lifetime_hours: u32,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_grace_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_grace_period_seconds)
+    }
+
+    pub fn subscription_token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.subscription_token_lifetime_hours.into())
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    pub backend: EmailBackend,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub max_messages_per_second: u32,
+    pub max_messages_per_day: u32,
+    pub smtp: SmtpSettings,
+    pub file_directory: String,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailBackend {
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: String,
+    pub password: Secret<String>,
+    pub require_tls: bool,
 }
 
 impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        match self.backend {
+            EmailBackend::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout),
+            ),
+            EmailBackend::Smtp => EmailClient::new(
+                sender_email,
+                SmtpTransport::new(
+                    &self.smtp.host,
+                    self.smtp.port,
+                    &self.smtp.username,
+                    &self.smtp.password,
+                    self.smtp.require_tls,
+                    timeout,
+                )
+                .expect("Invalid SMTP settings."),
+            ),
+            EmailBackend::File => EmailClient::new(
+                sender_email,
+                FileTransport::new(self.file_directory)
+                    .expect("Failed to set up the email file sink."),
+            ),
+        }
+    }
+
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.max_messages_per_second, self.max_messages_per_day)
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -94,6 +158,26 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliverySettings {
+    pub concurrency: u32,
+    /// Values above 1 make the worker send through the email client's batch API.
+    pub batch_size: u32,
+    pub max_attempts: u32,
+    pub backoff_base_milliseconds: u64,
+    pub backoff_max_milliseconds: u64,
+}
+
+impl DeliverySettings {
+    pub fn backoff_base(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.backoff_base_milliseconds)
+    }
+
+    pub fn backoff_max(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.backoff_max_milliseconds)
+    }
+}
+
 impl DatabaseSettings {
     pub fn with_db(&self) -> PgConnectOptions {
         let mut option = self.without_db().database(&self.database_name);
@@ -110,7 +194,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
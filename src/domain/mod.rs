@@ -1,7 +1,9 @@
 mod new_subcriber;
 mod subcriber_email;
 mod subcriber_name;
+mod subscriber_attributes;
 
 pub use new_subcriber::NewSubscriber;
 pub use subcriber_email::SubscriberEmail;
 pub use subcriber_name::SubscriberName;
+pub use subscriber_attributes::SubscriberAttributes;
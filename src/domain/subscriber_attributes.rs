@@ -0,0 +1,97 @@
+use serde_json::{Map, Value};
+
+/// Free-form key/value pairs attached to a subscriber, e.g. `country=NZ`.
+/// Names are limited to ASCII letters, digits and underscores.
+#[derive(Debug, Default)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn parse_name(name: &str) -> Result<String, String> {
+        let name = name.trim();
+        let is_valid = !name.is_empty()
+            && name.len() <= 64
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_valid {
+            Ok(name.to_owned())
+        } else {
+            Err(format!(
+                "Invalid attribute name {}, use letters, digits and underscores",
+                name
+            ))
+        }
+    }
+
+    /// Parses `name=value` lines as typed into the admin forms; blank lines
+    /// are skipped.
+    pub fn parse_lines(text: &str) -> Result<Self, String> {
+        let mut attributes = Self::default();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Expected name=value, got {}", line.trim()))?;
+            attributes.insert(name, value)?;
+        }
+        Ok(attributes)
+    }
+
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let name = Self::parse_name(name)?;
+        self.0.insert(name, Value::String(value.trim().to_owned()));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Renders a stored attributes object as `name=value, ...`.
+    pub fn describe(attributes: &Value) -> String {
+        match attributes.as_object() {
+            Some(attributes) => attributes
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value.as_str().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => String::new(),
+        }
+    }
+}
+
+impl From<SubscriberAttributes> for Value {
+    fn from(attributes: SubscriberAttributes) -> Self {
+        Value::Object(attributes.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberAttributes;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn names_with_letters_digits_and_underscores_are_valid() {
+        assert_ok!(SubscriberAttributes::parse_name("signup_source2"));
+    }
+
+    #[test]
+    fn names_with_other_characters_are_rejected() {
+        for name in ["", " ", "sign-up", "plan name", "{{plan}}"] {
+            assert_err!(SubscriberAttributes::parse_name(name));
+        }
+    }
+
+    #[test]
+    fn lines_are_parsed_into_trimmed_pairs() {
+        let attributes = SubscriberAttributes::parse_lines("country = NZ\n\nplan=pro\n").unwrap();
+
+        assert_eq!(
+            serde_json::Value::from(attributes),
+            serde_json::json!({"country": "NZ", "plan": "pro"})
+        );
+    }
+
+    #[test]
+    fn lines_without_an_equal_sign_are_rejected() {
+        assert_err!(SubscriberAttributes::parse_lines("country"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{to_message, Email, EmailTransport};
+use crate::domain::SubscriberEmail;
+
+/// Writes every message as an `.eml` file into a directory instead of
+/// sending it, which is handy for local development.
+pub struct FileTransport {
+    transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, from: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        self.transport.send(to_message(from, email)?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, FileTransport},
+    };
+    use claim::assert_ok;
+    use fake::{faker::internet::en::SafeEmail, Fake};
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_into_the_directory() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
+            FileTransport::new(&directory).unwrap(),
+        );
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+
+        let outcome = email_client
+            .send_email(&recipient, "Newsletter title", "<p>Hello</p>", "Hello")
+            .await;
+        assert_ok!(outcome);
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("Subject: Newsletter title"));
+        assert!(content.contains(recipient.as_ref()));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+mod file;
+mod postmark;
+mod smtp;
+
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+use anyhow::Context;
+use lettre::message::{
+    header::{HeaderName, HeaderValue},
+    Mailbox, MultiPart, SinglePart,
+};
+
+use crate::domain::SubscriberEmail;
+
+/// Postmark rejects batch requests with more than 500 messages.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+pub struct Email<'a> {
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    /// `None` sends a plain-text only message.
+    pub html_body: Option<&'a str>,
+    pub text_body: &'a str,
+    /// Advertised through RFC 8058 `List-Unsubscribe` headers when set.
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+/// The value of the `List-Unsubscribe-Post` header that marks a
+/// `List-Unsubscribe` URL as supporting one-click unsubscription.
+pub const LIST_UNSUBSCRIBE_POST: &str = "List-Unsubscribe=One-Click";
+
+/// A backend capable of delivering emails.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, from: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error>;
+
+    /// Sends up to [`MAX_BATCH_SIZE`] emails, returning one result per email in
+    /// the same order. The outer error is reserved for failures that affect the
+    /// whole batch. Backends without a batch API send the emails one by one.
+    async fn send_batch(
+        &self,
+        from: &SubscriberEmail,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), anyhow::Error>>, anyhow::Error> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send(from, email).await);
+        }
+        Ok(results)
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        let email = Email {
+            to: recipient,
+            subject,
+            html_body: Some(html_content),
+            text_body: text_content,
+            unsubscribe_url: None,
+        };
+        self.send(&email).await
+    }
+
+    pub async fn send(&self, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        self.transport.send(&self.sender, email).await
+    }
+
+    /// Sends many emails, splitting them into batches of at most
+    /// [`MAX_BATCH_SIZE`]. A batch-wide error is reported for every email
+    /// of that batch so that callers can map results back one to one.
+    pub async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), anyhow::Error>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.transport.send_batch(&self.sender, chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => {
+                    let message = format!("{:#}", e);
+                    results.extend(chunk.iter().map(|_| Err(anyhow::anyhow!(message.clone()))))
+                }
+            }
+        }
+        results
+    }
+}
+
+fn to_message(from: &SubscriberEmail, email: &Email<'_>) -> Result<lettre::Message, anyhow::Error> {
+    let from: Mailbox = from.as_ref().parse().context("Invalid sender address.")?;
+    let to: Mailbox = email
+        .to
+        .as_ref()
+        .parse()
+        .context("Invalid recipient address.")?;
+    let mut builder = lettre::Message::builder()
+        .from(from)
+        .to(to)
+        .subject(email.subject);
+    if let Some(url) = email.unsubscribe_url {
+        builder = builder
+            .raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                format!("<{}>", url),
+            ))
+            .raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                LIST_UNSUBSCRIBE_POST.to_owned(),
+            ));
+    }
+    let message = match email.html_body {
+        Some(html_body) => builder.multipart(MultiPart::alternative_plain_html(
+            email.text_body.to_owned(),
+            html_body.to_owned(),
+        )),
+        None => builder.singlepart(SinglePart::plain(email.text_body.to_owned())),
+    }
+    .context("Failed to build email message.")?;
+    Ok(message)
+}
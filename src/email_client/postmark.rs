@@ -0,0 +1,418 @@
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+
+use super::{Email, EmailTransport, LIST_UNSUBSCRIBE_POST};
+use crate::domain::SubscriberEmail;
+
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, from: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest::new(from, email);
+        self.http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn send_batch(
+        &self,
+        from: &SubscriberEmail,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), anyhow::Error>>, anyhow::Error> {
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<_> = emails
+            .iter()
+            .map(|email| SendEmailRequest::new(from, email))
+            .collect();
+        let responses: Vec<SendEmailResponse> = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if responses.len() != emails.len() {
+            anyhow::bail!(
+                "Postmark returned {} results for a batch of {} emails",
+                responses.len(),
+                emails.len()
+            );
+        }
+        Ok(responses
+            .into_iter()
+            .map(|r| {
+                if r.error_code == 0 {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!(
+                        "Postmark error {}: {}",
+                        r.error_code,
+                        r.message
+                    ))
+                }
+            })
+            .collect())
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<&'a str>,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<MessageHeader>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageHeader {
+    name: &'static str,
+    value: String,
+}
+
+impl<'a> SendEmailRequest<'a> {
+    fn new(from: &'a SubscriberEmail, email: &Email<'a>) -> Self {
+        let headers = match email.unsubscribe_url {
+            Some(url) => vec![
+                MessageHeader {
+                    name: "List-Unsubscribe",
+                    value: format!("<{}>", url),
+                },
+                MessageHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: LIST_UNSUBSCRIBE_POST.to_owned(),
+                },
+            ],
+            None => vec![],
+        };
+        Self {
+            from: from.as_ref(),
+            to: email.to.as_ref(),
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    error_code: i64,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{Email, EmailClient, PostmarkTransport},
+    };
+    use claim::assert_err;
+    use claim::assert_ok;
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::Secret;
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Mock, MockServer, Respond, ResponseTemplate,
+    };
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, serde_json::Error> =
+                serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(200));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome: Result<(), anyhow::Error> = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        assert_err!(outcome);
+    }
+
+    /// Replies to a batch request with one successful result per message.
+    struct BatchResponder;
+
+    impl Respond for BatchResponder {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = body
+                .iter()
+                .map(|_| serde_json::json!({"ErrorCode": 0, "Message": "OK"}))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        }
+    }
+
+    fn batch(recipients: &[SubscriberEmail]) -> Vec<Email<'_>> {
+        recipients
+            .iter()
+            .map(|to| Email {
+                to,
+                subject: "Newsletter title",
+                html_body: Some("<p>Newsletter body</p>"),
+                text_body: "Newsletter body",
+                unsubscribe_url: None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_advertises_one_click_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+
+        Mock::given(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send(&Email {
+                to: &recipient,
+                subject: "Newsletter title",
+                html_body: Some("<p>Newsletter body</p>"),
+                text_body: "Newsletter body",
+                unsubscribe_url: Some("https://example.com/unsubscribe?token=abc"),
+            })
+            .await;
+        assert_ok!(outcome);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe?token=abc>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = vec![email(), email()];
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(BatchResponder)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&batch(&recipients)).await;
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert_ok!(result);
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_maps_per_message_errors_back_to_each_email() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = vec![email(), email()];
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 300, "Message": "Invalid email request"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&batch(&recipients)).await;
+        assert_ok!(&results[0]);
+        assert_err!(&results[1]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_emails_into_batches_of_at_most_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients: Vec<_> = (0..501).map(|_| email()).collect();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(BatchResponder)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&batch(&recipients)).await;
+        assert_eq!(results.len(), 501);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_every_email_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = vec![email(), email()];
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&batch(&recipients)).await;
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert_err!(result);
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            PostmarkTransport::new(
+                base_url,
+                Secret::new(Faker.fake()),
+                std::time::Duration::from_millis(200),
+            ),
+        )
+    }
+}
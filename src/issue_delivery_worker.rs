@@ -1,119 +1,701 @@
-use sqlx::{PgPool, Postgres, Transaction};
-use std::time::Duration;
-use tracing::{field::display, Span};
+use chrono::Utc;
+use rand::Rng;
+use sqlx::{postgres::PgHasArrayType, PgPool, Postgres, Transaction};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{field::display, Instrument, Span};
 
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings,
+    archive::assign_slug,
+    configuration::{DeliverySettings, Settings},
     domain::SubscriberEmail,
-    email_client::EmailClient,
+    email_client::{Email, EmailClient},
     idempotency::{delete_all_idempotencys, delete_expire_idempotencys},
-    startup::get_connection_pool,
+    layouts::{
+        get_default_layout, get_layout_version, wrap_html, wrap_text, FooterLinks, LayoutVersion,
+    },
+    link_signer::LinkSigner,
+    merge_tags::MergeValues,
+    rate_limiter::{DailyReservation, RateLimiter},
+    routes::{
+        delete_stale_pending_subscribers, generate_subscription_token,
+        send_already_subscribed_email, send_confirmation_email, send_data_links_email, store_token,
+        SubscriberEmailKind, PREFERENCES_PATH, UNSUBSCRIBE_PATH,
+    },
+    startup::{get_connection_pool, get_worker_connection_pool},
 };
 
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    /// Today's sending budget is used up; the remaining tasks stay queued.
+    DailyCapReached,
 }
 
 #[tracing::instrument( skip_all,fields(newsletter_issues_id=tracing::field::Empty,subscriber_email=tracing::field::Empty),err)]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    rate_limiter: &RateLimiter,
+    link_signer: &LinkSigner,
+    settings: &DeliverySettings,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let tasks = dequeue_task(pool).await?;
-    if tasks.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
-    }
-    let (transaction, issue_id, email) = tasks.unwrap();
+    let (mut transaction, mut tasks) = dequeue_tasks(pool, 1).await?;
+    let task = match tasks.pop() {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
 
     Span::current()
-        .record("newsletter_issues_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(issue_id, pool).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                tracing::error!(
-                    error.cause = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber skipping.",
-                )
+        .record("newsletter_issues_id", display(task.issue_id))
+        .record("subscriber_email", display(&task.subscriber_email));
+
+    match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => match get_recipient(pool, task.issue_id, &email).await? {
+            Some(recipient) => {
+                let issue = get_issue(task.issue_id, pool).await?;
+                rate_limiter.acquire(pool, 1).await?;
+                let reservation = rate_limiter.reserve_daily_budget(pool, 1).await?;
+                if reservation.granted() == 0 {
+                    // Dropping the transaction releases the task untouched.
+                    return Ok(ExecutionOutcome::DailyCapReached);
+                }
+                let content = PersonalizedContent::new(&issue, &email, &recipient, link_signer);
+                match email_client
+                    .send(&content.email(&email, &issue.title))
+                    .await
+                {
+                    Ok(()) => complete_task(&mut transaction, &task, DeliveryOutcome::Sent).await?,
+                    Err(e) => {
+                        handle_failed_delivery(&mut transaction, &task, e, settings).await?;
+                        release_unsent(pool, rate_limiter, &reservation, 1).await;
+                    }
+                }
             }
-        }
+            None => complete_task(&mut transaction, &task, DeliveryOutcome::Skipped).await?,
+        },
         Err(e) => {
             tracing::error!(
                 error.cause = ?e,
                 error.message = %e,
                 "Skipping a confirmed subscriber.\
                 Their stored contant detail are invalid",
-            )
+            );
+            complete_task(&mut transaction, &task, DeliveryOutcome::Skipped).await?;
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Like [`try_execute_task`], but dequeues up to `batch_size` tasks at once
+/// and hands them to the email client as a single batch.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_batch(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    rate_limiter: &RateLimiter,
+    link_signer: &LinkSigner,
+    settings: &DeliverySettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let (mut transaction, tasks) = dequeue_tasks(pool, settings.batch_size).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", tasks.len());
+
+    let mut issues = HashMap::new();
+    let mut deliverable = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => {
+                let recipient = match get_recipient(pool, task.issue_id, &email).await? {
+                    Some(recipient) => recipient,
+                    None => {
+                        complete_task(&mut transaction, &task, DeliveryOutcome::Skipped).await?;
+                        continue;
+                    }
+                };
+                if let Entry::Vacant(entry) = issues.entry(task.issue_id) {
+                    entry.insert(get_issue(task.issue_id, pool).await?);
+                }
+                deliverable.push((task, email, recipient));
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause = ?e,
+                    error.message = %e,
+                    newsletter_issues_id = %task.issue_id,
+                    "Skipping a confirmed subscriber.\
+                    Their stored contant detail are invalid",
+                );
+                complete_task(&mut transaction, &task, DeliveryOutcome::Skipped).await?;
+            }
+        }
+    }
+
+    // Tasks beyond today's budget are left in the queue, untouched.
+    let reservation = rate_limiter
+        .reserve_daily_budget(pool, deliverable.len() as u32)
+        .await?;
+    deliverable.truncate(reservation.granted() as usize);
+
+    // The batch goes out in parts that fit the per-second budget.
+    let mut remaining = &deliverable[..];
+    let mut n_unsent = 0;
+    while !remaining.is_empty() {
+        let n = match rate_limiter.acquire(pool, remaining.len() as u32).await {
+            Ok(n) => n as usize,
+            Err(e) => {
+                // Bailing out would roll back the parts already sent, so the
+                // rest is left in the queue instead.
+                tracing::error!(
+                    error.cause = ?e,
+                    error.message = %e,
+                    "Failed to acquire the sending rate, leaving the rest of the batch queued.",
+                );
+                n_unsent += remaining.len() as u32;
+                break;
+            }
+        };
+        let (part, rest) = remaining.split_at(n);
+        remaining = rest;
+        let contents: Vec<_> = part
+            .iter()
+            .map(|(task, email, recipient)| {
+                PersonalizedContent::new(&issues[&task.issue_id], email, recipient, link_signer)
+            })
+            .collect();
+        let emails: Vec<_> = part
+            .iter()
+            .zip(&contents)
+            .map(|((task, email, _), content)| content.email(email, &issues[&task.issue_id].title))
+            .collect();
+        let results = email_client.send_batch(&emails).await;
+        for ((task, _, _), result) in part.iter().zip(results) {
+            match result {
+                Ok(()) => complete_task(&mut transaction, task, DeliveryOutcome::Sent).await?,
+                Err(e) => {
+                    handle_failed_delivery(&mut transaction, task, e, settings).await?;
+                    n_unsent += 1;
+                }
+            }
         }
     }
+    release_unsent(pool, rate_limiter, &reservation, n_unsent).await;
+
+    transaction.commit().await?;
+    if reservation.granted() == 0 {
+        Ok(ExecutionOutcome::DailyCapReached)
+    } else {
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+}
+
+/// Sends one email from the subscriber email queue: a confirmation link for a
+/// sign-up or a CSV import, a note to somebody who signed up again, or the
+/// links to a subscriber's data. A confirmation token is issued at send time, so the link stays valid for
+/// its full lifetime however long the email waited in the queue.
+#[tracing::instrument(skip_all, fields(subscriber_id = tracing::field::Empty), err)]
+pub async fn try_send_subscriber_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    rate_limiter: &RateLimiter,
+    link_signer: &LinkSigner,
+    base_url: &str,
+    settings: &DeliverySettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query!(
+        r#"
+        SELECT
+            q.subscriber_id,
+            q.list_id as "list_id?",
+            q.kind,
+            q.n_attempts,
+            s.email,
+            m.status as "status?"
+        FROM subscriber_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        LEFT JOIN list_memberships m
+            ON m.subscriber_id = q.subscriber_id AND m.list_id = q.list_id
+        WHERE q.next_attempt_at <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let task = match task {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("subscriber_id", display(task.subscriber_id));
 
-    delete_task(issue_id, transaction, &email).await?;
+    // Subscribers whose membership changed in the meantime get nothing: a
+    // confirmation only goes to a pending one, a reminder only to a
+    // confirmed one.
+    let kind = SubscriberEmailKind::parse(&task.kind);
+    let status = task.status.as_deref();
+    let is_due = match kind {
+        Some(SubscriberEmailKind::Confirmation) => status == Some("pending_confirmation"),
+        Some(SubscriberEmailKind::AlreadySubscribed) => status == Some("confirmed"),
+        Some(SubscriberEmailKind::DataRequest) => true,
+        None => false,
+    };
+    if is_due {
+        match SubscriberEmail::parse(task.email) {
+            Ok(email) => {
+                let layout = get_default_layout(pool).await?;
+                rate_limiter.acquire(pool, 1).await?;
+                let reservation = rate_limiter.reserve_daily_budget(pool, 1).await?;
+                if reservation.granted() == 0 {
+                    return Ok(ExecutionOutcome::DailyCapReached);
+                }
+                let subscription_token = generate_subscription_token();
+                let sent = match kind {
+                    Some(SubscriberEmailKind::AlreadySubscribed) => {
+                        send_already_subscribed_email(&email, email_client, layout.as_ref()).await
+                    }
+                    Some(SubscriberEmailKind::DataRequest) => {
+                        send_data_links_email(&email, email_client, link_signer, layout.as_ref())
+                            .await
+                    }
+                    _ => {
+                        send_confirmation_email(
+                            &email,
+                            email_client,
+                            base_url,
+                            &subscription_token,
+                            layout.as_ref(),
+                        )
+                        .await
+                    }
+                };
+                match sent {
+                    Ok(()) => {
+                        if let (Some(SubscriberEmailKind::Confirmation), Some(list_id)) =
+                            (kind, task.list_id)
+                        {
+                            store_token(
+                                &mut transaction,
+                                task.subscriber_id,
+                                list_id,
+                                &subscription_token,
+                            )
+                            .await?
+                        }
+                    }
+                    Err(e) => {
+                        release_unsent(pool, rate_limiter, &reservation, 1).await;
+                        let n_attempts = task.n_attempts + 1;
+                        if n_attempts < settings.max_attempts as i32 {
+                            tracing::error!(
+                                error.cause = ?e,
+                                error.message = %e,
+                                n_attempts,
+                                "Failed to send an email to a subscriber, rescheduling.",
+                            );
+                            let next_attempt_at = Utc::now()
+                                + chrono::Duration::from_std(retry_delay(
+                                    task.n_attempts,
+                                    settings,
+                                ))?;
+                            sqlx::query!(
+                                r#"
+                                UPDATE subscriber_email_queue
+                                SET n_attempts = n_attempts + 1, next_attempt_at = $4
+                                WHERE subscriber_id = $1
+                                    AND list_id IS NOT DISTINCT FROM $2
+                                    AND kind = $3
+                                "#,
+                                task.subscriber_id,
+                                task.list_id,
+                                task.kind,
+                                next_attempt_at
+                            )
+                            .execute(&mut transaction)
+                            .await?;
+                            transaction.commit().await?;
+                            return Ok(ExecutionOutcome::TaskCompleted);
+                        }
+                        tracing::error!(
+                            error.cause = ?e,
+                            error.message = %e,
+                            n_attempts,
+                            "Failed to send an email to a subscriber, giving up.",
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause = ?e,
+                    error.message = %e,
+                    "Skipping a pending subscriber.\
+                    Their stored contant detail are invalid",
+                );
+            }
+        }
+    }
 
+    sqlx::query!(
+        r#"
+        DELETE FROM subscriber_email_queue
+        WHERE subscriber_id = $1 AND list_id IS NOT DISTINCT FROM $2 AND kind = $3
+        "#,
+        task.subscriber_id,
+        task.list_id,
+        task.kind
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct Task {
+    issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i32,
+}
+
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    limit: u32,
+) -> Result<(PgTransaction, Vec<Task>), anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        Task,
         r#"
-        SELECT newsletter_issues_id , subscriber_email 
+        SELECT newsletter_issues_id as issue_id, subscriber_email, n_attempts
         FROM issues_delivery_queue
+        WHERE next_attempt_at <= now()
         FOR UPDATE
         SKIP LOCKED
-        LIMIT 1
+        LIMIT $1
     "#,
+        i64::from(limit)
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issues_id,
-            r.subscriber_email,
-        )))
+    Ok((transaction, tasks))
+}
+
+/// Gives messages that did not go out back to the daily budget, so that only
+/// accepted sends count against the cap. A failure here merely leaves the
+/// budget short, so it is logged rather than returned.
+async fn release_unsent(
+    pool: &PgPool,
+    rate_limiter: &RateLimiter,
+    reservation: &DailyReservation,
+    n_unsent: u32,
+) {
+    if let Err(e) = rate_limiter
+        .release_daily_budget(pool, reservation, n_unsent)
+        .await
+    {
+        tracing::error!(
+            error.cause = ?e,
+            error.message = %e,
+            n_unsent,
+            "Failed to give unsent messages back to the daily budget.",
+        );
+    }
+}
+
+async fn handle_failed_delivery(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    e: anyhow::Error,
+    settings: &DeliverySettings,
+) -> Result<(), anyhow::Error> {
+    let n_attempts = task.n_attempts + 1;
+    if n_attempts >= settings.max_attempts as i32 {
+        tracing::error!(
+            error.cause = ?e,
+            error.message = %e,
+            newsletter_issues_id = %task.issue_id,
+            n_attempts,
+            "Failed to deliver issue to a confirmed subscriber, giving up.",
+        );
+        move_task_to_failed_deliveries(transaction, task, &e.to_string()).await
     } else {
-        Ok(None)
+        tracing::error!(
+            error.cause = ?e,
+            error.message = %e,
+            newsletter_issues_id = %task.issue_id,
+            n_attempts,
+            "Failed to deliver issue to a confirmed subscriber, rescheduling.",
+        );
+        reschedule_task(transaction, task, &e.to_string(), settings).await
     }
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    issue_id: Uuid,
-    mut transaction: PgTransaction,
-    email: &str,
+async fn reschedule_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    error: &str,
+    settings: &DeliverySettings,
 ) -> Result<(), anyhow::Error> {
+    let next_attempt_at =
+        Utc::now() + chrono::Duration::from_std(retry_delay(task.n_attempts, settings))?;
     sqlx::query!(
         r#"
-        DELETE FROM issues_delivery_queue
+        UPDATE issues_delivery_queue
+        SET
+            n_attempts = n_attempts + 1,
+            attempts = attempts || ROW(now(), $3)::delivery_attempt,
+            last_error = $3,
+            next_attempt_at = $4
         WHERE
             newsletter_issues_id = $1 AND subscriber_email = $2
     "#,
-        issue_id,
-        email
+        task.issue_id,
+        task.subscriber_email,
+        error,
+        next_attempt_at
     )
-    .execute(&mut transaction)
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_task_to_failed_deliveries(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        WITH failed AS (
+            DELETE FROM issues_delivery_queue
+            WHERE
+                newsletter_issues_id = $1 AND subscriber_email = $2
+            RETURNING newsletter_issues_id, subscriber_email, n_attempts, attempts
+        )
+        INSERT INTO failed_deliveries (
+            newsletter_issues_id,
+            subscriber_email,
+            n_attempts,
+            attempts,
+            last_error,
+            failed_at
+        )
+        SELECT
+            newsletter_issues_id,
+            subscriber_email,
+            n_attempts + 1,
+            attempts || ROW(now(), $3)::delivery_attempt,
+            $3,
+            now()
+        FROM failed
+        ON CONFLICT (newsletter_issues_id, subscriber_email) DO UPDATE SET
+            n_attempts = EXCLUDED.n_attempts,
+            attempts = EXCLUDED.attempts,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+    "#,
+        task.issue_id,
+        task.subscriber_email,
+        error
+    )
+    .execute(transaction)
     .await?;
+    Ok(())
+}
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "delivery_attempt")]
+pub struct DeliveryAttempt {
+    pub attempted_at: chrono::DateTime<Utc>,
+    pub error: String,
+}
 
+impl PgHasArrayType for DeliveryAttempt {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_delivery_attempt")
+    }
+}
+
+/// Exponential backoff with jitter: the delay doubles with every failed
+/// attempt, is capped at `backoff_max`, and is then scaled by a random
+/// factor in `[0.5, 1.0]` so that failed tasks do not retry in lockstep.
+fn retry_delay(n_attempts: i32, settings: &DeliverySettings) -> Duration {
+    let exponent = n_attempts.clamp(0, 16) as u32;
+    let delay = settings
+        .backoff_base()
+        .saturating_mul(2u32.pow(exponent))
+        .min(settings.backoff_max());
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+pub enum DeliveryOutcome {
+    Sent,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn complete_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    outcome: DeliveryOutcome,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        WITH completed AS (
+            DELETE FROM issues_delivery_queue
+            WHERE
+                newsletter_issues_id = $1 AND subscriber_email = $2
+            RETURNING newsletter_issues_id, subscriber_email, n_attempts
+        )
+        INSERT INTO issue_deliveries (
+            newsletter_issues_id,
+            subscriber_email,
+            outcome,
+            n_attempts,
+            delivered_at
+        )
+        SELECT newsletter_issues_id, subscriber_email, $3, n_attempts + 1, now()
+        FROM completed
+        ON CONFLICT (newsletter_issues_id, subscriber_email) DO UPDATE SET
+            outcome = EXCLUDED.outcome,
+            n_attempts = EXCLUDED.n_attempts,
+            delivered_at = EXCLUDED.delivered_at
+    "#,
+        task.issue_id,
+        task.subscriber_email,
+        outcome.as_str()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Fans an issue out to every confirmed subscriber of its lists who also
+/// matches its segment, if any; pending and unsubscribed ones are left out.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    nesletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+           INSERT INTO issues_delivery_queue (
+                newsletter_issues_id,
+               subscriber_email
+        )
+        SELECT DISTINCT $1::uuid, s.email
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issue_lists il ON il.list_id = m.list_id
+        JOIN newsletter_issues i ON i.newsletter_issues_id = il.newsletter_issues_id
+        LEFT JOIN segments seg ON seg.segment_id = i.segment_id
+        WHERE
+            il.newsletter_issues_id = $1
+            AND m.status = 'confirmed'
+            AND s.status = 'confirmed'
+            AND (
+                seg.segment_id IS NULL
+                OR (
+                    s.attributes @> seg.attributes
+                    AND (seg.subscribed_from IS NULL OR s.subscribed_at >= seg.subscribed_from)
+                    AND (seg.subscribed_before IS NULL OR s.subscribed_at < seg.subscribed_before)
+                )
+            )
+        "#,
+        nesletter_issue_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Starts sending the scheduled issues whose `send_at` has passed by
+/// enqueueing their delivery tasks. Returns how many issues were started.
+#[tracing::instrument(skip_all, err)]
+pub async fn enqueue_due_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let due = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND send_at <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        "#
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for issue in &due {
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'sending', published_at = now()
+            WHERE newsletter_issues_id = $1
+            "#,
+            issue.newsletter_issues_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        assign_slug(&mut transaction, issue.newsletter_issues_id).await?;
+        enqueue_delivery_tasks(&mut transaction, issue.newsletter_issues_id).await?;
+    }
     transaction.commit().await?;
+    Ok(due.len())
+}
+
+/// Marks issues whose queue has drained as sent, and puts them back to
+/// sending when failed deliveries are requeued.
+#[tracing::instrument(skip_all, err)]
+pub async fn update_sending_progress(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        WITH progress AS (
+            SELECT
+                i.newsletter_issues_id,
+                CASE
+                    WHEN EXISTS (
+                        SELECT 1 FROM issues_delivery_queue q
+                        WHERE q.newsletter_issues_id = i.newsletter_issues_id
+                    ) THEN 'sending'
+                    ELSE 'sent'
+                END AS status
+            FROM newsletter_issues i
+            WHERE i.status IN ('sending', 'sent')
+        )
+        UPDATE newsletter_issues i
+        SET status = p.status
+        FROM progress p
+        WHERE i.newsletter_issues_id = p.newsletter_issues_id AND i.status <> p.status
+        "#
+    )
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
@@ -121,14 +703,121 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    /// The layout version pinned when the issue was published.
+    layout: Option<LayoutVersion>,
+}
+
+/// What a subscriber chose on their preferences page, as far as the
+/// delivery of one issue is concerned, and what its merge tags are filled
+/// in with.
+struct Recipient {
+    name: String,
+    attributes: serde_json::Value,
+    plain_text_only: bool,
+}
+
+/// Looks the subscriber up again at send time: `None` if they have left
+/// every list the issue goes to since it was queued.
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    pool: &PgPool,
+    issue_id: Uuid,
+    email: &SubscriberEmail,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query!(
+        r#"
+        SELECT s.name, s.attributes, s.email_format
+        FROM subscriptions s
+        WHERE
+            s.email = $2
+            AND s.status = 'confirmed'
+            AND EXISTS (
+                SELECT 1
+                FROM list_memberships m
+                JOIN newsletter_issue_lists il ON il.list_id = m.list_id
+                WHERE
+                    m.subscriber_id = s.id
+                    AND m.status = 'confirmed'
+                    AND il.newsletter_issues_id = $1
+            )
+        "#,
+        issue_id,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| Recipient {
+        name: r.name,
+        attributes: r.attributes,
+        plain_text_only: r.email_format == "plain_text",
+    });
+    Ok(recipient)
+}
+
+/// An issue's body as one subscriber receives it: merge tags filled in,
+/// wrapped in the issue's layout, in the format they chose and with their
+/// own signed preferences and unsubscribe links.
+struct PersonalizedContent {
+    html_body: Option<String>,
+    text_body: String,
+    unsubscribe_url: String,
+}
+
+impl PersonalizedContent {
+    fn new(
+        issue: &NewsletterIssue,
+        email: &SubscriberEmail,
+        recipient: &Recipient,
+        link_signer: &LinkSigner,
+    ) -> Self {
+        let preferences_url = link_signer.signed_url(PREFERENCES_PATH, email.as_ref());
+        let unsubscribe_url = link_signer.signed_url(UNSUBSCRIBE_PATH, email.as_ref());
+        let values = MergeValues {
+            name: &recipient.name,
+            email: email.as_ref(),
+            unsubscribe_url: &unsubscribe_url,
+            attributes: &recipient.attributes,
+        };
+        let links = FooterLinks {
+            preferences_url: &preferences_url,
+            unsubscribe_url: &unsubscribe_url,
+        };
+        let layout = issue.layout.as_ref();
+        let html_body = (!recipient.plain_text_only).then(|| {
+            wrap_html(
+                layout,
+                &values.render_html(&issue.html_content),
+                Some(&links),
+            )
+        });
+        let text_body = wrap_text(
+            layout,
+            &values.render_text(&issue.text_content),
+            Some(&links),
+        );
+        Self {
+            html_body,
+            text_body,
+            unsubscribe_url,
+        }
+    }
+
+    fn email<'a>(&'a self, to: &'a SubscriberEmail, subject: &'a str) -> Email<'a> {
+        Email {
+            to,
+            subject,
+            html_body: self.html_body.as_deref(),
+            text_body: &self.text_body,
+            unsubscribe_url: Some(&self.unsubscribe_url),
+        }
+    }
 }
 
 #[tracing::instrument(skip_all)]
 async fn get_issue(issue_id: Uuid, pool: &PgPool) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+    let issue = sqlx::query!(
         r#"
-        SELECT title ,text_content,html_content
+        SELECT title ,text_content,html_content, layout_version_id
         FROM newsletter_issues
         WHERE
             newsletter_issues_id = $1
@@ -137,36 +826,186 @@ async fn get_issue(issue_id: Uuid, pool: &PgPool) -> Result<NewsletterIssue, any
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    let layout = match issue.layout_version_id {
+        Some(layout_version_id) => Some(get_layout_version(pool, layout_version_id).await?),
+        None => None,
+    };
+    Ok(NewsletterIssue {
+        title: issue.title,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        layout,
+    })
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    rate_limiter: Arc<RateLimiter>,
+    link_signer: LinkSigner,
+    base_url: String,
+    settings: DeliverySettings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    // A task that has been dequeued is always seen through to its commit;
+    // shutdown is only checked between tasks and while idling.
+    while !shutdown.is_cancelled() {
+        let outcome = if settings.batch_size > 1 {
+            try_execute_batch(&pool, &email_client, &rate_limiter, &link_signer, &settings).await
+        } else {
+            try_execute_task(&pool, &email_client, &rate_limiter, &link_signer, &settings).await
+        };
+        // Newsletter issues go first; queued subscriber emails are sent
+        // once there is nothing else to deliver.
+        let outcome = match outcome {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                try_send_subscriber_email(
+                    &pool,
+                    &email_client,
+                    &rate_limiter,
+                    &link_signer,
+                    &base_url,
+                    &settings,
+                )
+                .await
+            }
+            outcome => outcome,
+        };
+        let pause = match outcome {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 let _ = delete_all_idempotencys(&pool).await;
-                tokio::time::sleep(Duration::from_secs(10)).await
+                Duration::from_secs(10)
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::DailyCapReached) => Duration::from_secs(60),
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+            Err(_) => Duration::from_secs(1),
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.cancelled() => {}
         }
     }
+    Ok(())
 }
 
-async fn clear_idempotencys_loop(pool: PgPool) -> Result<(), anyhow::Error> {
-    loop {
+async fn scheduler_loop(pool: PgPool, shutdown: CancellationToken) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        let _ = enqueue_due_issues(&pool).await;
+        let _ = update_sending_progress(&pool).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(10)) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+    Ok(())
+}
+
+async fn clear_idempotencys_loop(
+    pool: PgPool,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
         let _ = delete_expire_idempotencys(&pool).await;
-        tokio::time::sleep(Duration::from_secs(300)).await
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(300)) => {}
+            _ = shutdown.cancelled() => {}
+        }
     }
+    Ok(())
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+async fn purge_stale_subscribers_loop(
+    pool: PgPool,
+    token_lifetime: chrono::Duration,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        if let Err(e) = delete_stale_pending_subscribers(&pool, token_lifetime).await {
+            tracing::error!(
+                error.cause = ?e,
+                error.message = %e,
+                "Failed to purge stale pending subscribers.",
+            )
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(3600)) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+    Ok(())
 }
 
-pub async fn run_clear_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+/// Runs `delivery.concurrency` workers that drain the queue side by side.
+/// `FOR UPDATE SKIP LOCKED` keeps them, and workers running in other
+/// processes, from picking up the same task. Next to them runs the
+/// scheduler, which enqueues scheduled issues once they are due.
+///
+/// Once `shutdown` is cancelled the workers finish the task they are on; any
+/// worker still busy after the grace period is aborted, which rolls back its
+/// transaction and leaves the task in the queue.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let n_workers = configuration.delivery.concurrency.max(1);
+    let connection_pool = get_worker_connection_pool(&configuration.database, n_workers);
+    let rate_limiter = Arc::new(configuration.email_client.rate_limiter());
+    let email_client = Arc::new(configuration.email_client.client());
+    let link_signer = LinkSigner::new(
+        configuration.application.base_url.clone(),
+        configuration.application.hmac_secret.clone(),
+    );
+    let mut workers = JoinSet::new();
+    for worker_id in 0..n_workers {
+        workers.spawn(
+            worker_loop(
+                connection_pool.clone(),
+                email_client.clone(),
+                rate_limiter.clone(),
+                link_signer.clone(),
+                configuration.application.base_url.clone(),
+                configuration.delivery.clone(),
+                shutdown.clone(),
+            )
+            .instrument(tracing::info_span!("Delivery worker", worker_id)),
+        );
+    }
+    workers.spawn(
+        scheduler_loop(connection_pool.clone(), shutdown.clone())
+            .instrument(tracing::info_span!("Issue scheduler")),
+    );
+    let grace_period = configuration.application.shutdown_grace_period();
+    tokio::select! {
+        outcome = join_workers(&mut workers) => outcome,
+        _ = async {
+            shutdown.cancelled().await;
+            tokio::time::sleep(grace_period).await
+        } => {
+            tracing::warn!("Delivery workers did not stop within the grace period, aborting them");
+            workers.shutdown().await;
+            Ok(())
+        }
+    }
+}
+
+async fn join_workers(
+    workers: &mut JoinSet<Result<(), anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    while let Some(outcome) = workers.join_next().await {
+        outcome??;
+    }
+    Ok(())
+}
+
+pub async fn run_clear_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    clear_idempotencys_loop(connection_pool).await
+    let token_lifetime = configuration.application.subscription_token_lifetime();
+    tokio::try_join!(
+        clear_idempotencys_loop(connection_pool.clone(), shutdown.clone()),
+        purge_stale_subscribers_loop(connection_pool, token_lifetime, shutdown),
+    )?;
+    Ok(())
 }
@@ -0,0 +1,253 @@
+use htmlescape::encode_minimal;
+use sqlx::{PgExecutor, PgPool};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::markdown::render_markdown;
+
+/// A named wrapper for outgoing emails, as listed in the admin area.
+/// Transactional emails use the default layout; issues use the one picked
+/// when they are published.
+pub struct Layout {
+    pub layout_id: Uuid,
+    pub name: String,
+    pub is_default: bool,
+    /// The number of the latest version.
+    pub version: i32,
+}
+
+/// One version of a layout: a header and a footer written in Markdown and
+/// a postal address. Versions are never changed once saved, so an issue
+/// keeps the rendering it was published with.
+pub struct LayoutVersion {
+    pub layout_version_id: Uuid,
+    pub version: i32,
+    pub header: String,
+    pub footer: String,
+    pub postal_address: String,
+}
+
+/// The links at the bottom of a newsletter issue; transactional emails have
+/// none.
+pub struct FooterLinks<'a> {
+    pub preferences_url: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+#[tracing::instrument(name = "Get layouts", skip(pool))]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<Layout>, sqlx::Error> {
+    sqlx::query_as!(
+        Layout,
+        r#"
+        SELECT l.layout_id, l.name, l.is_default, MAX(v.version) as "version!"
+        FROM layouts l
+        JOIN layout_versions v ON v.layout_id = l.layout_id
+        GROUP BY l.layout_id
+        ORDER BY l.is_default DESC, l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get layout version", skip(executor))]
+pub async fn get_layout_version(
+    executor: impl PgExecutor<'_>,
+    layout_version_id: Uuid,
+) -> Result<LayoutVersion, sqlx::Error> {
+    sqlx::query_as!(
+        LayoutVersion,
+        r#"
+        SELECT layout_version_id, version, header, footer, postal_address
+        FROM layout_versions
+        WHERE layout_version_id = $1
+        "#,
+        layout_version_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+#[tracing::instrument(name = "Get latest layout version", skip(executor))]
+pub async fn get_latest_version(
+    executor: impl PgExecutor<'_>,
+    layout_id: Uuid,
+) -> Result<Option<LayoutVersion>, sqlx::Error> {
+    sqlx::query_as!(
+        LayoutVersion,
+        r#"
+        SELECT layout_version_id, version, header, footer, postal_address
+        FROM layout_versions
+        WHERE layout_id = $1
+        ORDER BY version DESC
+        LIMIT 1
+        "#,
+        layout_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The latest version of the default layout, if one has been chosen.
+#[tracing::instrument(name = "Get default layout", skip(executor))]
+pub async fn get_default_layout(
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<LayoutVersion>, sqlx::Error> {
+    sqlx::query_as!(
+        LayoutVersion,
+        r#"
+        SELECT v.layout_version_id, v.version, v.header, v.footer, v.postal_address
+        FROM layout_versions v
+        JOIN layouts l ON l.layout_id = v.layout_id
+        WHERE l.is_default
+        ORDER BY v.version DESC
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Pins the latest version of the requested layout; `None` sends without a
+/// layout.
+#[tracing::instrument(name = "Resolve layout version", skip(executor))]
+pub async fn resolve_layout_version(
+    executor: impl PgExecutor<'_>,
+    requested: Option<Uuid>,
+) -> Result<Option<LayoutVersion>, ResolveLayoutError> {
+    let layout_id = match requested {
+        Some(layout_id) => layout_id,
+        None => return Ok(None),
+    };
+    get_latest_version(executor, layout_id)
+        .await?
+        .map(Some)
+        .ok_or(ResolveLayoutError::UnknownLayout)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveLayoutError {
+    #[error("Unknown layout.")]
+    UnknownLayout,
+    #[error(transparent)]
+    UnexpectedError(#[from] sqlx::Error),
+}
+
+/// Puts an HTML body inside the layout, followed by the footer links and
+/// the postal address.
+pub fn wrap_html(
+    layout: Option<&LayoutVersion>,
+    body: &str,
+    links: Option<&FooterLinks>,
+) -> String {
+    let mut html = String::new();
+    if let Some(layout) = layout {
+        html.push_str(&render_markdown(&layout.header).html);
+    }
+    html.push_str(body);
+    if let Some(layout) = layout {
+        html.push('\n');
+        html.push_str(&render_markdown(&layout.footer).html);
+    }
+    if let Some(links) = links {
+        write!(
+            html,
+            "\n<p><a href=\"{}\">Manage your preferences</a> | <a href=\"{}\">Unsubscribe</a></p>",
+            encode_minimal(links.preferences_url),
+            encode_minimal(links.unsubscribe_url)
+        )
+        .unwrap();
+    }
+    if let Some(layout) = layout {
+        let address_lines: Vec<_> = layout
+            .postal_address
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(encode_minimal)
+            .collect();
+        if !address_lines.is_empty() {
+            write!(html, "\n<p>{}</p>", address_lines.join("<br />")).unwrap();
+        }
+    }
+    html
+}
+
+/// The plain-text counterpart of [`wrap_html`].
+pub fn wrap_text(
+    layout: Option<&LayoutVersion>,
+    body: &str,
+    links: Option<&FooterLinks>,
+) -> String {
+    let mut parts = Vec::new();
+    if let Some(layout) = layout {
+        parts.push(render_markdown(&layout.header).text);
+    }
+    parts.push(body.to_owned());
+    if let Some(layout) = layout {
+        parts.push(render_markdown(&layout.footer).text);
+    }
+    if let Some(links) = links {
+        parts.push(format!(
+            "Manage your preferences: {}\nUnsubscribe: {}",
+            links.preferences_url, links.unsubscribe_url
+        ));
+    }
+    if let Some(layout) = layout {
+        parts.push(layout.postal_address.trim().to_owned());
+    }
+    parts.retain(|part| !part.is_empty());
+    parts.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wrap_html, wrap_text, FooterLinks, LayoutVersion};
+    use uuid::Uuid;
+
+    fn layout() -> LayoutVersion {
+        LayoutVersion {
+            layout_version_id: Uuid::new_v4(),
+            version: 1,
+            header: "# Weekly <b>digest</b>".into(),
+            footer: "Thanks for reading!".into(),
+            postal_address: "1 Main St\nSpringfield".into(),
+        }
+    }
+
+    const LINKS: FooterLinks<'static> = FooterLinks {
+        preferences_url: "https://example.com/preferences?a=1&b=2",
+        unsubscribe_url: "https://example.com/unsubscribe",
+    };
+
+    #[test]
+    fn without_a_layout_only_the_links_are_added() {
+        assert_eq!(wrap_html(None, "<p>Body</p>", None), "<p>Body</p>");
+        assert_eq!(
+            wrap_text(None, "Body", Some(&LINKS)),
+            "Body\n\nManage your preferences: https://example.com/preferences?a=1&b=2\n\
+             Unsubscribe: https://example.com/unsubscribe"
+        );
+    }
+
+    #[test]
+    fn the_html_body_goes_between_the_header_and_the_footer() {
+        let html = wrap_html(Some(&layout()), "<p>Body</p>", Some(&LINKS));
+        assert_eq!(
+            html,
+            "<h1>Weekly <b>digest</b></h1>\n<p>Body</p>\n<p>Thanks for reading!</p>\n\n\
+             <p><a href=\"https://example.com/preferences?a=1&amp;b=2\">Manage your preferences</a> | \
+             <a href=\"https://example.com/unsubscribe\">Unsubscribe</a></p>\n\
+             <p>1 Main St<br />Springfield</p>"
+        );
+    }
+
+    #[test]
+    fn the_text_body_goes_between_the_header_and_the_footer() {
+        let text = wrap_text(Some(&layout()), "Body", None);
+        assert_eq!(
+            text,
+            "Weekly digest\n\nBody\n\nThanks for reading!\n\n1 Main St\nSpringfield"
+        );
+    }
+}
@@ -8,4 +8,13 @@ pub mod authentication;
 pub mod session_state;
 pub mod utils;
 pub mod idempotency;
+pub mod archive;
 pub mod issue_delivery_worker;
+pub mod layouts;
+pub mod link_signer;
+pub mod mailing_lists;
+pub mod markdown;
+pub mod merge_tags;
+pub mod rate_limiter;
+pub mod segments;
+pub mod shutdown;
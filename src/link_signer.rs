@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+/// Builds links that let a subscriber act on their own subscription without
+/// logging in. Each link carries an HMAC of the action and the subscriber's
+/// email, so it cannot be forged or reused for another address or action.
+#[derive(Clone)]
+pub struct LinkSigner {
+    base_url: String,
+    /// Keyed with the link subkey; cloned for every signature.
+    keyed_mac: Hmac<Sha256>,
+}
+
+impl LinkSigner {
+    /// `secret` is the application's HMAC secret, which also signs the flash
+    /// message cookies: links are signed with a key derived from it, so a
+    /// token can never be mistaken for a cookie signature or the other way
+    /// round.
+    pub fn new(base_url: String, secret: Secret<String>) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(b"links");
+        let keyed_mac = Hmac::<Sha256>::new_from_slice(&mac.finalize().into_bytes())
+            .expect("HMAC accepts keys of any size");
+        Self {
+            base_url,
+            keyed_mac,
+        }
+    }
+
+    /// `path` doubles as the action the signature is bound to.
+    ///
+    /// These links deliberately never expire: unsubscribe and preference
+    /// links sit in old emails and must keep working for as long as the
+    /// email is kept. Use [`LinkSigner::signed_url_until`] for anything that
+    /// hands out more than a subscription change.
+    pub fn signed_url(&self, path: &str, email: &str) -> String {
+        format!("{}{}", self.base_url, self.signed_path(path, email))
+    }
+
+    /// Like [`LinkSigner::signed_url`], without the base URL, for links and
+    /// form actions on our own pages.
+    pub fn signed_path(&self, path: &str, email: &str) -> String {
+        format!(
+            "{}?email={}&token={}",
+            path,
+            urlencoding::encode(email),
+            hex::encode(self.mac(path, email).finalize().into_bytes())
+        )
+    }
+
+    pub fn verify(&self, path: &str, email: &str, token: &str) -> Result<(), anyhow::Error> {
+        let token = hex::decode(token)?;
+        self.mac(path, email).verify_slice(&token)?;
+        Ok(())
+    }
+
+    /// Like [`LinkSigner::signed_url`], for links that hand out more than a
+    /// subscription change and therefore stop working at `expires_at`.
+    pub fn signed_url_until(&self, path: &str, email: &str, expires_at: DateTime<Utc>) -> String {
+        let expires = expires_at.timestamp();
+        format!(
+            "{}{}?email={}&expires={}&token={}",
+            self.base_url,
+            path,
+            urlencoding::encode(email),
+            expires,
+            hex::encode(
+                self.expiring_mac(path, email, expires)
+                    .finalize()
+                    .into_bytes()
+            )
+        )
+    }
+
+    pub fn verify_until(
+        &self,
+        path: &str,
+        email: &str,
+        expires: i64,
+        token: &str,
+    ) -> Result<(), anyhow::Error> {
+        let token = hex::decode(token)?;
+        self.expiring_mac(path, email, expires)
+            .verify_slice(&token)?;
+        if Utc::now().timestamp() > expires {
+            anyhow::bail!("The link has expired.");
+        }
+        Ok(())
+    }
+
+    fn mac(&self, path: &str, email: &str) -> Hmac<Sha256> {
+        let mut mac = self.keyed_mac.clone();
+        mac.update(path.as_bytes());
+        mac.update(b"\0");
+        mac.update(email.as_bytes());
+        mac
+    }
+
+    fn expiring_mac(&self, path: &str, email: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = self.mac(path, email);
+        mac.update(b"\0");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinkSigner;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use hmac::{Hmac, Mac};
+    use secrecy::Secret;
+    use sha2::Sha256;
+
+    fn signer() -> LinkSigner {
+        LinkSigner::new(
+            "http://localhost".into(),
+            Secret::new("a-very-secret-key".into()),
+        )
+    }
+
+    fn token_of(url: &str) -> String {
+        url.split("token=").nth(1).unwrap().to_string()
+    }
+
+    #[test]
+    fn a_signed_url_verifies_for_the_same_path_and_email() {
+        let url = signer().signed_url("/subscriptions/unsubscribe", "ursula@example.com");
+
+        assert!(url.starts_with(
+            "http://localhost/subscriptions/unsubscribe?email=ursula%40example.com&token="
+        ));
+        assert_ok!(signer().verify(
+            "/subscriptions/unsubscribe",
+            "ursula@example.com",
+            &token_of(&url)
+        ));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_another_email_or_path() {
+        let token =
+            token_of(&signer().signed_url("/subscriptions/unsubscribe", "ursula@example.com"));
+
+        assert_err!(signer().verify("/subscriptions/unsubscribe", "le.guin@example.com", &token));
+        assert_err!(signer().verify("/subscriptions/erase", "ursula@example.com", &token));
+        assert_err!(signer().verify("/subscriptions/unsubscribe", "ursula@example.com", "zz"));
+    }
+
+    #[test]
+    fn tokens_are_not_signed_with_the_application_secret_itself() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"a-very-secret-key").unwrap();
+        mac.update(b"/subscriptions/unsubscribe\0ursula@example.com");
+        let token = hex::encode(mac.finalize().into_bytes());
+
+        assert_err!(signer().verify("/subscriptions/unsubscribe", "ursula@example.com", &token));
+    }
+
+    #[test]
+    fn an_expiring_token_is_rejected_once_expired_or_tampered_with() {
+        let expires_at = Utc::now() + Duration::hours(1);
+        let url =
+            signer().signed_url_until("/subscriptions/export", "ursula@example.com", expires_at);
+        let token = token_of(&url);
+        let expires = expires_at.timestamp();
+
+        assert_ok!(signer().verify_until(
+            "/subscriptions/export",
+            "ursula@example.com",
+            expires,
+            &token
+        ));
+        assert_err!(signer().verify_until(
+            "/subscriptions/export",
+            "ursula@example.com",
+            expires + 3600,
+            &token
+        ));
+        assert_err!(signer().verify("/subscriptions/export", "ursula@example.com", &token));
+
+        let expired_at = Utc::now() - Duration::hours(1);
+        let url =
+            signer().signed_url_until("/subscriptions/export", "ursula@example.com", expired_at);
+        assert_err!(signer().verify_until(
+            "/subscriptions/export",
+            "ursula@example.com",
+            expired_at.timestamp(),
+            &token_of(&url)
+        ));
+    }
+}
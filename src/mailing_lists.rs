@@ -0,0 +1,63 @@
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// A list subscribers can join, e.g. a weekly digest or release announcements.
+/// Sign-ups and issues that do not name a list go to the default one.
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub is_default: bool,
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, is_default
+        FROM lists
+        ORDER BY is_default DESC, name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Checks that every requested list exists, falling back to the default list
+/// when none is requested.
+#[tracing::instrument(name = "Resolve mailing lists", skip(executor))]
+pub async fn resolve_list_ids(
+    executor: impl PgExecutor<'_>,
+    requested: &[Uuid],
+) -> Result<Vec<Uuid>, ResolveListsError> {
+    let mut requested = requested.to_vec();
+    requested.sort();
+    requested.dedup();
+    let list_ids = sqlx::query!(
+        r#"
+        SELECT list_id
+        FROM lists
+        WHERE
+            (cardinality($1::uuid[]) = 0 AND is_default)
+            OR list_id = ANY($1)
+        "#,
+        &requested
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|r| r.list_id)
+    .collect::<Vec<_>>();
+    if list_ids.is_empty() || list_ids.len() < requested.len() {
+        return Err(ResolveListsError::UnknownList);
+    }
+    Ok(list_ids)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveListsError {
+    #[error("Unknown mailing list.")]
+    UnknownList,
+    #[error(transparent)]
+    UnexpectedError(#[from] sqlx::Error),
+}
@@ -2,6 +2,7 @@ use std::fmt::{Debug, Display};
 
 use tokio::task::JoinError;
 use zero2prod::issue_delivery_worker::{run_clear_until_stopped, run_worker_until_stopped};
+use zero2prod::shutdown::cancel_on_shutdown_signal;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 use zero2prod::configuration::get_configuration;
@@ -14,15 +15,36 @@ async fn main() -> anyhow::Result<()> {
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read config");
+    let shutdown = cancel_on_shutdown_signal();
+    if std::env::args().nth(1).as_deref() == Some("worker") {
+        // Only drain the delivery queue, so that workers can be scaled out
+        // as separate processes next to the API.
+        let outcome = run_worker_until_stopped(configuration, shutdown).await;
+        report_exit("Background worker", Ok(outcome));
+        return Ok(());
+    }
     let server = Application::build(configuration.clone()).await?;
-    let application = server.run_until_stopped();
-    let worker = run_worker_until_stopped(configuration.clone());
-    let clear = run_clear_until_stopped(configuration);
-    tokio::select! {
-        o = application => report_exit("API",Ok(o)),
-        o = worker => report_exit("Background worker",Ok(o)),
-        o = clear => report_exit("clear",Ok(o)),
-    };
+    let application = tokio::spawn(server.run_until_stopped(shutdown.clone()));
+    let worker = tokio::spawn(run_worker_until_stopped(
+        configuration.clone(),
+        shutdown.clone(),
+    ));
+    let clear = tokio::spawn(run_clear_until_stopped(configuration, shutdown.clone()));
+    // If any of them exits, the others are asked to wind down as well.
+    tokio::join!(
+        async {
+            report_exit("API", application.await);
+            shutdown.cancel();
+        },
+        async {
+            report_exit("Background worker", worker.await);
+            shutdown.cancel();
+        },
+        async {
+            report_exit("clear", clear.await);
+            shutdown.cancel();
+        },
+    );
     Ok(())
 }
 
@@ -0,0 +1,262 @@
+use htmlescape::encode_minimal;
+use pulldown_cmark::{html, Event, LinkType, Options, Parser, Tag};
+
+/// The two parts of an issue as rendered from a single Markdown body.
+pub struct RenderedMarkdown {
+    /// Sanitised: raw HTML, scripts and event handlers in the source are
+    /// dropped.
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render_markdown(source: &str) -> RenderedMarkdown {
+    let options = Options::ENABLE_STRIKETHROUGH;
+    let mut unsafe_html = String::new();
+    html::push_html(
+        &mut unsafe_html,
+        Parser::new_ext(source, options).map(keep_merge_tags_in_links),
+    );
+    RenderedMarkdown {
+        html: ammonia::clean(&unsafe_html),
+        text: PlainText::render(Parser::new_ext(source, options)),
+    }
+}
+
+/// pulldown-cmark percent-encodes braces in link targets, which would turn
+/// `[Unsubscribe]({{ unsubscribe_url }})` into a link the merge tag renderer
+/// no longer recognises; those links are written out by hand instead.
+fn keep_merge_tags_in_links(event: Event) -> Event {
+    match event {
+        Event::Start(Tag::Link(link_type, url, title)) if url.contains("{{") => {
+            let scheme = if link_type == LinkType::Email {
+                "mailto:"
+            } else {
+                ""
+            };
+            let title = if title.is_empty() {
+                String::new()
+            } else {
+                format!(" title=\"{}\"", encode_minimal(&title))
+            };
+            Event::Html(format!("<a href=\"{}{}\"{}>", scheme, encode_minimal(&url), title).into())
+        }
+        event => event,
+    }
+}
+
+/// Writes Markdown events out as readable plain text: blocks separated by
+/// blank lines, list markers and quote markers kept, and link targets
+/// spelled out after their text.
+#[derive(Default)]
+struct PlainText {
+    out: String,
+    /// Written at the start of every line, e.g. `"> "` inside a quote.
+    prefixes: Vec<String>,
+    /// The marker of a list item whose first line is yet to be written.
+    pending_marker: Option<String>,
+    /// The next number of each open list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// The target of each open link and where its text starts.
+    links: Vec<(String, usize)>,
+    /// The closing tag of an inline `<script>` or `<style>` element whose
+    /// content is being skipped, as the HTML part drops it too.
+    skip_until: Option<&'static str>,
+}
+
+impl PlainText {
+    fn render<'a>(events: impl Iterator<Item = Event<'a>>) -> String {
+        let mut text = Self::default();
+        for event in events {
+            if let Some(closing_tag) = text.skip_until {
+                if matches!(&event, Event::Html(html) if html.to_ascii_lowercase().contains(closing_tag))
+                {
+                    text.skip_until = None;
+                }
+                continue;
+            }
+            match event {
+                Event::Start(tag) => text.start(tag),
+                Event::End(tag) => text.end(tag),
+                Event::Text(s) | Event::Code(s) => text.push(&s),
+                Event::SoftBreak | Event::HardBreak => text.out.push('\n'),
+                Event::Rule => {
+                    text.block();
+                    text.push("----");
+                }
+                // Raw HTML has no plain-text counterpart.
+                Event::Html(html) => text.skip_until = skipped_element(&html),
+                Event::FootnoteReference(_) | Event::TaskListMarker(_) => {}
+            }
+        }
+        text.out.trim().to_owned()
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::Heading(..) => self.block(),
+            Tag::BlockQuote => {
+                self.block();
+                self.prefixes.push("> ".into());
+            }
+            Tag::CodeBlock(_) => {
+                self.block();
+                self.prefixes.push("    ".into());
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.block();
+                }
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                self.end_line();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_owned(),
+                };
+                self.prefixes.push(" ".repeat(marker.len()));
+                self.pending_marker = Some(marker);
+            }
+            Tag::Link(_, url, _) => self.links.push((url.into_string(), self.out.len())),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::BlockQuote | Tag::CodeBlock(_) => {
+                self.prefixes.pop();
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+            }
+            Tag::Item => {
+                self.pending_marker = None;
+                self.prefixes.pop();
+            }
+            Tag::Link(..) => {
+                if let Some((url, start)) = self.links.pop() {
+                    let is_autolink = self.out[start..] == url
+                        || url.strip_prefix("mailto:") == Some(&self.out[start..]);
+                    if !url.is_empty() && !is_autolink {
+                        self.push(&format!(" ({})", url));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts a paragraph-like block on a new line, one blank line after
+    /// the previous one. The first block of a list item goes right after
+    /// its marker.
+    fn block(&mut self) {
+        if self.out.is_empty() || self.pending_marker.is_some() {
+            return;
+        }
+        self.end_line();
+        if !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            if line.is_empty() {
+                continue;
+            }
+            if self.out.is_empty() || self.out.ends_with('\n') {
+                self.write_prefixes();
+            }
+            self.out.push_str(line);
+        }
+    }
+
+    fn write_prefixes(&mut self) {
+        let n_prefixes = self.prefixes.len();
+        for (i, prefix) in self.prefixes.iter().enumerate() {
+            match self.pending_marker.take() {
+                Some(marker) if i + 1 == n_prefixes => self.out.push_str(&marker),
+                marker => {
+                    self.pending_marker = marker;
+                    self.out.push_str(prefix);
+                }
+            }
+        }
+    }
+}
+
+/// Inline HTML is split into one event per tag, with the element's content
+/// in between as ordinary text.
+fn skipped_element(html: &str) -> Option<&'static str> {
+    let html = html.to_ascii_lowercase();
+    [("<script", "</script"), ("<style", "</style")]
+        .into_iter()
+        .find(|(opening_tag, closing_tag)| {
+            html.starts_with(opening_tag) && !html.contains(closing_tag)
+        })
+        .map(|(_, closing_tag)| closing_tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn raw_html_and_scripts_are_stripped_from_the_html_part() {
+        let rendered = render_markdown(
+            "Hello <script>alert(1)</script>\n\n<img src=x onerror=alert(1)>\n\n[click](javascript:alert(1))",
+        );
+        assert!(!rendered.html.contains("<script"));
+        assert!(!rendered.html.contains("onerror"));
+        assert!(!rendered.html.contains("javascript:"));
+    }
+
+    #[test]
+    fn the_text_part_keeps_the_structure_readable() {
+        let rendered = render_markdown(
+            "# Release notes\n\nWe shipped **two** things:\n\n1. Lists\n2. [Docs](https://example.com/docs)\n\n> Quoted\n> text\n\n- a\n  - b\n\n```\nlet x = 1;\n```\n",
+        );
+        assert_eq!(
+            rendered.text,
+            "Release notes\n\n\
+             We shipped two things:\n\n\
+             1. Lists\n\
+             2. Docs (https://example.com/docs)\n\n\
+             > Quoted\n\
+             > text\n\n\
+             - a\n\
+             \x20 - b\n\n\
+             \x20   let x = 1;"
+        );
+    }
+
+    #[test]
+    fn merge_tags_survive_rendering() {
+        let rendered = render_markdown("Hi **{{ name }}**");
+        assert_eq!(rendered.html, "<p>Hi <strong>{{ name }}</strong></p>\n");
+        assert_eq!(rendered.text, "Hi {{ name }}");
+    }
+
+    #[test]
+    fn merge_tags_survive_in_link_targets() {
+        let rendered = render_markdown("[Unsubscribe]({{unsubscribe_url}} \"Bye\")");
+        assert_eq!(
+            rendered.html,
+            "<p><a href=\"{{unsubscribe_url}}\" title=\"Bye\" rel=\"noopener noreferrer\">Unsubscribe</a></p>\n"
+        );
+        assert_eq!(rendered.text, "Unsubscribe ({{unsubscribe_url}})");
+    }
+}
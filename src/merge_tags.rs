@@ -0,0 +1,183 @@
+use htmlescape::encode_minimal;
+use sqlx::PgExecutor;
+
+use crate::domain::SubscriberAttributes;
+
+/// Tags every subscriber has a value for; any other tag must name a
+/// subscriber attribute.
+const BUILTIN_TAGS: [&str; 3] = ["name", "email", "unsubscribe_url"];
+
+#[derive(Debug)]
+enum Piece<'a> {
+    Text(&'a str),
+    Tag(&'a str),
+}
+
+/// Splits an issue body into literal text and `{{ tag }}` placeholders.
+fn parse(content: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        pieces.push(Piece::Text(&rest[..start]));
+        let after_open = &rest[start + 2..];
+        // The messages end up in the admin's flash, so they leave the
+        // offending text out rather than echo markup back.
+        let end = after_open
+            .find("}}")
+            .ok_or("A merge tag is opened with {{ but never closed.")?;
+        let tag = after_open[..end].trim();
+        SubscriberAttributes::parse_name(tag).map_err(|_| {
+            "Merge tags can only contain letters, digits and underscores.".to_string()
+        })?;
+        pieces.push(Piece::Tag(tag));
+        rest = &after_open[end + 2..];
+    }
+    pieces.push(Piece::Text(rest));
+    Ok(pieces)
+}
+
+/// Rejects issue bodies with malformed tags or tags that are neither
+/// built in nor set as an attribute on any subscriber.
+#[tracing::instrument(name = "Validate merge tags", skip_all)]
+pub async fn validate_merge_tags(
+    executor: impl PgExecutor<'_>,
+    contents: &[&str],
+) -> Result<(), MergeTagError> {
+    let mut tags = Vec::new();
+    for content in contents {
+        for piece in parse(content).map_err(MergeTagError::InvalidTag)? {
+            if let Piece::Tag(tag) = piece {
+                if !BUILTIN_TAGS.contains(&tag) {
+                    tags.push(tag.to_owned());
+                }
+            }
+        }
+    }
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let known = sqlx::query!(
+        r#"
+        SELECT DISTINCT k.key as "key!"
+        FROM subscriptions s, jsonb_object_keys(s.attributes) AS k(key)
+        WHERE k.key = ANY($1)
+        "#,
+        &tags
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|r| r.key)
+    .collect::<Vec<_>>();
+    match tags.iter().find(|tag| !known.contains(tag)) {
+        Some(tag) => Err(MergeTagError::InvalidTag(format!(
+            "Unknown merge tag {{{{ {} }}}}.",
+            tag
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The values one recipient's copy of an issue is rendered with.
+pub struct MergeValues<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub attributes: &'a serde_json::Value,
+}
+
+impl MergeValues<'_> {
+    fn get(&self, tag: &str) -> &str {
+        match tag {
+            "name" => self.name,
+            "email" => self.email,
+            "unsubscribe_url" => self.unsubscribe_url,
+            // A subscriber without the attribute gets an empty string.
+            attribute => self
+                .attributes
+                .get(attribute)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn render_text(&self, content: &str) -> String {
+        self.render(content, |value| value.to_owned())
+    }
+
+    pub fn render_html(&self, content: &str) -> String {
+        self.render(content, encode_minimal)
+    }
+
+    fn render(&self, content: &str, escape: impl Fn(&str) -> String) -> String {
+        // Issues saved before merge tags existed may not parse; they go out
+        // verbatim, as they always did.
+        let pieces = match parse(content) {
+            Ok(pieces) => pieces,
+            Err(_) => return content.to_owned(),
+        };
+        let mut rendered = String::with_capacity(content.len());
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => rendered.push_str(text),
+                Piece::Tag(tag) => rendered.push_str(&escape(self.get(tag))),
+            }
+        }
+        rendered
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MergeTagError {
+    #[error("{0}")]
+    InvalidTag(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] sqlx::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, MergeValues};
+    use claim::{assert_err, assert_ok};
+
+    fn values(attributes: &serde_json::Value) -> MergeValues<'_> {
+        MergeValues {
+            name: "Ursula <Le Guin>",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?a=1&b=2",
+            attributes,
+        }
+    }
+
+    #[test]
+    fn tags_are_filled_in_with_or_without_spaces() {
+        let attributes = serde_json::json!({ "country": "fr" });
+        let rendered = values(&attributes).render_text("Hi {{name}}, from {{ country }}!");
+        assert_eq!(rendered, "Hi Ursula <Le Guin>, from fr!");
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let attributes = serde_json::json!({});
+        let rendered = values(&attributes)
+            .render_html("<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">x</a>");
+        assert_eq!(
+            rendered,
+            "<p>Hi Ursula &lt;Le Guin&gt;</p><a href=\"https://example.com/unsubscribe?a=1&amp;b=2\">x</a>"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_render_as_empty_strings() {
+        let attributes = serde_json::json!({});
+        assert_eq!(values(&attributes).render_text("[{{ country }}]"), "[]");
+    }
+
+    #[test]
+    fn unclosed_or_malformed_tags_are_rejected() {
+        assert_err!(parse("Hi {{ name"));
+        assert_err!(parse("Hi {{ first name }}"));
+        assert_err!(parse("Hi {{}}"));
+        assert_ok!(parse("No tags } here {"));
+    }
+}
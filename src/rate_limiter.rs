@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use sqlx::PgPool;
+
+/// Keeps outbound email within the provider's limits. Both the per-second
+/// rate and the daily cap are tracked in Postgres, so that they are shared by
+/// every worker and survive restarts.
+pub struct RateLimiter {
+    messages_per_second: u32,
+    messages_per_day: u32,
+}
+
+/// Messages taken out of one day's budget.
+#[derive(Debug)]
+pub struct DailyReservation {
+    day: NaiveDate,
+    granted: u32,
+}
+
+impl DailyReservation {
+    pub fn granted(&self) -> u32 {
+        self.granted
+    }
+}
+
+impl RateLimiter {
+    pub fn new(messages_per_second: u32, messages_per_day: u32) -> Self {
+        Self {
+            messages_per_second,
+            messages_per_day,
+        }
+    }
+
+    pub fn messages_per_second(&self) -> u32 {
+        self.messages_per_second
+    }
+
+    pub fn messages_per_day(&self) -> u32 {
+        self.messages_per_day
+    }
+
+    /// Takes up to `n` messages out of the current second's budget and
+    /// returns how many were granted, waiting for the next second while the
+    /// budget is used up. At least one message is granted when `n` is not
+    /// zero, but never more than the per-second rate, so a larger batch has
+    /// to be sent in several parts.
+    #[tracing::instrument(skip(self, pool))]
+    pub async fn acquire(&self, pool: &PgPool, n: u32) -> Result<u32, anyhow::Error> {
+        if n == 0 {
+            return Ok(0);
+        }
+        let rate = i64::from(self.messages_per_second.max(1));
+        loop {
+            let mut transaction = pool.begin().await?;
+            // The database clock is the one every worker agrees on.
+            let window = sqlx::query!(
+                r#"
+                SELECT
+                    window_start,
+                    n_sent,
+                    date_trunc('second', clock_timestamp()) as "current_window!",
+                    clock_timestamp() as "now!"
+                FROM email_send_rate
+                FOR UPDATE
+                "#
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+            let n_sent = if window.window_start == window.current_window {
+                i64::from(window.n_sent)
+            } else {
+                0
+            };
+            let granted = i64::from(n).min(rate - n_sent).max(0);
+            if granted > 0 {
+                sqlx::query!(
+                    r#"
+                    UPDATE email_send_rate SET window_start = $1, n_sent = $2
+                    "#,
+                    window.current_window,
+                    (n_sent + granted) as i32
+                )
+                .execute(&mut transaction)
+                .await?;
+                transaction.commit().await?;
+                return Ok(granted as u32);
+            }
+            transaction.rollback().await?;
+            let next_window = window.current_window + chrono::Duration::seconds(1);
+            let wait = (next_window - window.now)
+                .to_std()
+                .unwrap_or(Duration::ZERO);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Reserves up to `n` messages from today's budget; a reservation of zero
+    /// means the daily cap has been reached. Messages that end up not being
+    /// sent are given back with [`RateLimiter::release_daily_budget`].
+    #[tracing::instrument(skip(self, pool))]
+    pub async fn reserve_daily_budget(
+        &self,
+        pool: &PgPool,
+        n: u32,
+    ) -> Result<DailyReservation, anyhow::Error> {
+        let today = Utc::now().date_naive();
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO email_send_budget (day, n_sent)
+            VALUES ($1, 0)
+            ON CONFLICT DO NOTHING
+            "#,
+            today
+        )
+        .execute(&mut transaction)
+        .await?;
+        let n_sent = sqlx::query!(
+            r#"
+            SELECT n_sent FROM email_send_budget WHERE day = $1 FOR UPDATE
+            "#,
+            today
+        )
+        .fetch_one(&mut transaction)
+        .await?
+        .n_sent;
+        let remaining = (i64::from(self.messages_per_day) - i64::from(n_sent)).max(0);
+        let granted = i64::from(n).min(remaining) as i32;
+        if granted > 0 {
+            sqlx::query!(
+                r#"
+                UPDATE email_send_budget SET n_sent = n_sent + $2 WHERE day = $1
+                "#,
+                today,
+                granted
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(DailyReservation {
+            day: today,
+            granted: granted as u32,
+        })
+    }
+
+    /// Gives `n` messages of a reservation back to the budget of the day they
+    /// were reserved on, once their send has failed or been put off.
+    #[tracing::instrument(skip(self, pool))]
+    pub async fn release_daily_budget(
+        &self,
+        pool: &PgPool,
+        reservation: &DailyReservation,
+        n: u32,
+    ) -> Result<(), anyhow::Error> {
+        let n = n.min(reservation.granted);
+        if n == 0 {
+            return Ok(());
+        }
+        sqlx::query!(
+            r#"
+            UPDATE email_send_budget SET n_sent = GREATEST(n_sent - $2, 0) WHERE day = $1
+            "#,
+            reservation.day,
+            n as i32
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, pool))]
+    pub async fn remaining_daily_budget(&self, pool: &PgPool) -> Result<i64, anyhow::Error> {
+        let n_sent = sqlx::query!(
+            r#"
+            SELECT n_sent FROM email_send_budget WHERE day = $1
+            "#,
+            Utc::now().date_naive()
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|r| r.n_sent)
+        .unwrap_or(0);
+        Ok((i64::from(self.messages_per_day) - i64::from(n_sent)).max(0))
+    }
+}
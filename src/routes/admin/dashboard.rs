@@ -5,14 +5,21 @@ use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{authentication::UserId, utils::e500};
+use crate::{authentication::UserId, rate_limiter::RateLimiter, utils::e500};
 
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
     pool: web::Data<PgPool>,
+    rate_limiter: web::Data<RateLimiter>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let remaining_budget = rate_limiter
+        .remaining_daily_budget(&pool)
+        .await
+        .map_err(e500)?;
+    let messages_per_day = rate_limiter.messages_per_day();
+    let messages_per_second = rate_limiter.messages_per_second();
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -29,9 +36,16 @@ pub async fn admin_dashboard(
 
 <body>
         <p>Welcome {username}</p>
+        <p>Email budget remaining today: {remaining_budget} of {messages_per_day} ({messages_per_second} per second)</p>
         <p>Avaliable actions: </p>
         <ol>
             <li><a href="/admin/password">Change password</a></li>
+            <li><a href="/admin/email">Email address</a></li>
+            <li><a href="/admin/subscribers">Subscribers</a></li>
+            <li><a href="/admin/lists">Mailing lists</a></li>
+            <li><a href="/admin/segments">Segments</a></li>
+            <li><a href="/admin/layouts">Layouts</a></li>
+            <li><a href="/admin/failed_deliveries">Failed deliveries</a></li>
             <li>
                  <form name="logoutForm" action="/admin/logout" method="post">
                     <input type="submit" value="Logout"/>
@@ -0,0 +1,75 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{authentication::UserId, utils::e500};
+
+pub async fn email_address_form(
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let email = get_user_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+
+    let response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Email address</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>Test copies of newsletter issues are sent to this address.</p>
+    <form action="/admin/email" method="post">
+        <label>Email address
+            <input type="email" placeholder="Enter email address" name="email" value="{email}" />
+        </label>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            email = encode_minimal(&email),
+        ));
+
+    Ok(response)
+}
+
+#[tracing::instrument(name = "Get user email", skip(pool))]
+pub async fn get_user_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve a user's email address.")?;
+
+    Ok(row.email)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{email_address_form, get_user_email};
+pub use post::change_email_address;
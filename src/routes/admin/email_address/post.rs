@@ -0,0 +1,43 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::UserId,
+    domain::SubscriberEmail,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Change email address", skip(pool, form))]
+pub async fn change_email_address(
+    form: web::Form<FormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(_) => {
+            FlashMessage::error("Please enter a valid email address.").send();
+            return Ok(see_other("/admin/email"));
+        }
+    };
+    sqlx::query!(
+        r#"
+        UPDATE users SET email = $1 WHERE user_id = $2
+        "#,
+        email.as_ref(),
+        *user_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the user's email address.")
+    .map_err(e500)?;
+    FlashMessage::info("Your email address has been updated.").send();
+    Ok(see_other("/admin/email"))
+}
@@ -0,0 +1,135 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{issue_delivery_worker::DeliveryAttempt, utils::e500};
+
+pub async fn failed_deliveries(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let deliveries = get_failed_deliveries(&pool).await.map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for delivery in &deliveries {
+        let mut attempts_html = String::new();
+        for attempt in &delivery.attempts {
+            writeln!(
+                attempts_html,
+                "<li>{}: {}</li>",
+                attempt.attempted_at.to_rfc3339(),
+                encode_minimal(&attempt.error)
+            )
+            .unwrap();
+        }
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{email}</td>
+                <td>{n_attempts}</td>
+                <td>{last_error}</td>
+                <td>{failed_at}</td>
+                <td><ol>{attempts_html}</ol></td>
+                <td>
+                    <form action="/admin/failed_deliveries/requeue" method="post">
+                        <input hidden type="text" name="newsletter_issues_id" value="{issue_id}" />
+                        <input hidden type="text" name="subscriber_email" value="{email}" />
+                        <button type="submit">Requeue</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_minimal(&delivery.title),
+            email = encode_minimal(&delivery.subscriber_email),
+            n_attempts = delivery.n_attempts,
+            last_error = encode_minimal(&delivery.last_error),
+            failed_at = delivery.failed_at.to_rfc3339(),
+            issue_id = delivery.newsletter_issues_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Failed deliveries</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>{n_failed} failed deliveries</p>
+    <form action="/admin/failed_deliveries/requeue_all" method="post">
+        <button type="submit">Requeue all</button>
+    </form>
+    <table>
+        <tr>
+            <th>Issue</th>
+            <th>Subscriber</th>
+            <th>Attempts</th>
+            <th>Last error</th>
+            <th>Failed at</th>
+            <th>History</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            n_failed = deliveries.len(),
+        )))
+}
+
+struct FailedDelivery {
+    newsletter_issues_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i32,
+    attempts: Vec<DeliveryAttempt>,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get failed deliveries", skip(pool))]
+async fn get_failed_deliveries(pool: &PgPool) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            f.newsletter_issues_id,
+            i.title,
+            f.subscriber_email,
+            f.n_attempts,
+            f.attempts as "attempts!: Vec<DeliveryAttempt>",
+            f.last_error,
+            f.failed_at
+        FROM failed_deliveries f
+        JOIN newsletter_issues i ON i.newsletter_issues_id = f.newsletter_issues_id
+        ORDER BY f.failed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve failed deliveries.")?;
+    Ok(deliveries)
+}
@@ -0,0 +1,83 @@
+use ::actix_web::HttpResponse;
+use actix_web::web;
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issues_id: Uuid,
+    subscriber_email: String,
+}
+
+/// The delivery starts over with a fresh retry budget, but keeps the attempts
+/// made so far: if it fails again, the earlier errors are still on record.
+#[tracing::instrument(name = "Requeue a failed delivery", skip(pool, form))]
+pub async fn requeue_failed_delivery(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM failed_deliveries
+            WHERE
+                newsletter_issues_id = $1 AND subscriber_email = $2
+            RETURNING newsletter_issues_id, subscriber_email, attempts, last_error
+        )
+        INSERT INTO issues_delivery_queue (
+            newsletter_issues_id,
+            subscriber_email,
+            attempts,
+            last_error
+        )
+        SELECT newsletter_issues_id, subscriber_email, attempts, last_error FROM requeued
+        ON CONFLICT DO NOTHING
+        "#,
+        form.newsletter_issues_id,
+        form.subscriber_email
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to requeue a failed delivery.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_requeued > 0 {
+        FlashMessage::info("The delivery has been requeued.").send();
+    } else {
+        FlashMessage::error("The delivery could not be found.").send();
+    }
+    Ok(see_other("/admin/failed_deliveries"))
+}
+
+#[tracing::instrument(name = "Requeue all failed deliveries", skip(pool))]
+pub async fn requeue_all_failed_deliveries(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM failed_deliveries
+            RETURNING newsletter_issues_id, subscriber_email, attempts, last_error
+        )
+        INSERT INTO issues_delivery_queue (
+            newsletter_issues_id,
+            subscriber_email,
+            attempts,
+            last_error
+        )
+        SELECT newsletter_issues_id, subscriber_email, attempts, last_error FROM requeued
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to requeue failed deliveries.")
+    .map_err(e500)?
+    .rows_affected();
+    FlashMessage::info(format!("{} deliveries have been requeued.", n_requeued)).send();
+    Ok(see_other("/admin/failed_deliveries"))
+}
@@ -0,0 +1,165 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    layouts::{get_latest_version, get_layouts},
+    utils::{e404, e500},
+};
+
+pub async fn layouts_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows_html = String::new();
+    for layout in get_layouts(&pool).await.map_err(e500)? {
+        let make_default = if layout.is_default {
+            String::new()
+        } else {
+            format!(
+                r#"<form action="/admin/layouts/{}/default" method="post"><button type="submit">Make default</button></form>"#,
+                layout.layout_id
+            )
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/layouts/{id}">{name}</a>{default}</td>
+                <td>{version}</td>
+                <td>{make_default}</td>
+            </tr>"#,
+            id = layout.layout_id,
+            name = encode_minimal(&layout.name),
+            default = if layout.is_default { " (default)" } else { "" },
+            version = layout.version,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Layouts</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>Issues are sent in the layout picked when they are published; the default layout also wraps confirmation and other transactional emails.</p>
+    <table>
+        <tr>
+            <th>Layout</th>
+            <th>Version</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/layouts" method="post">
+        <label>Name
+            <input type="text" placeholder="Enter layout name" name="name" />
+        </label>
+        {fields_html}
+        <button type="submit">Create layout</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            fields_html = layout_fields("", "", ""),
+        )))
+}
+
+pub async fn edit_layout_form(
+    layout_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = sqlx::query!(
+        r#"
+        SELECT name FROM layouts WHERE layout_id = $1
+        "#,
+        *layout_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the layout.")
+    .map_err(e500)?
+    .ok_or_else(|| e404("Layout not found"))?
+    .name;
+    let latest = get_latest_version(pool.get_ref(), *layout_id)
+        .await
+        .context("Failed to retrieve the layout.")
+        .map_err(e500)?
+        .ok_or_else(|| e404("Layout not found"))?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Edit layout</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>{name}, version {version}</p>
+    <p>Saving adds a new version; issues already published keep the version they were sent with.</p>
+    <form action="/admin/layouts/{id}" method="post">
+        {fields_html}
+        <button type="submit">Save new version</button>
+    </form>
+    <p><a href="/admin/layouts">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            name = encode_minimal(&name),
+            version = latest.version,
+            id = *layout_id,
+            fields_html = layout_fields(&latest.header, &latest.footer, &latest.postal_address),
+        )))
+}
+
+fn layout_fields(header: &str, footer: &str, postal_address: &str) -> String {
+    format!(
+        r#"<label>Header (Markdown)
+            <textarea name="header">{}</textarea>
+        </label>
+        <label>Footer (Markdown)
+            <textarea name="footer">{}</textarea>
+        </label>
+        <label>Postal address
+            <textarea name="postal_address">{}</textarea>
+        </label>"#,
+        encode_minimal(header),
+        encode_minimal(footer),
+        encode_minimal(postal_address),
+    )
+}
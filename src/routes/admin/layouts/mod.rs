@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{edit_layout_form, layouts_page};
+pub use post::{create_layout, make_default_layout, save_layout};
@@ -0,0 +1,178 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct CreateFormData {
+    name: String,
+    #[serde(flatten)]
+    fields: LayoutFields,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LayoutFields {
+    header: String,
+    footer: String,
+    postal_address: String,
+}
+
+#[tracing::instrument(name = "Create a layout", skip(pool, form))]
+pub async fn create_layout(
+    form: web::Form<CreateFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let CreateFormData { name, fields } = form.0;
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Please enter a name for the layout.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let layout_id = Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO layouts (layout_id, name) VALUES ($1, $2)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        layout_id,
+        name
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to create a layout.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error("A layout with that name already exists.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    insert_version(&mut transaction, layout_id, &fields)
+        .await
+        .context("Failed to store the first version of a layout.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a layout.")
+        .map_err(e500)?;
+    FlashMessage::info("The layout has been created.").send();
+    Ok(see_other("/admin/layouts"))
+}
+
+/// Layouts are never edited in place: saving adds a version, so that the
+/// issues already published keep the one they were sent with.
+#[tracing::instrument(name = "Save a layout", skip(pool, form))]
+pub async fn save_layout(
+    layout_id: web::Path<Uuid>,
+    form: web::Form<LayoutFields>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let saved = insert_version(&mut transaction, *layout_id, &form)
+        .await
+        .context("Failed to store a new version of a layout.")
+        .map_err(e500)?;
+    if !saved {
+        FlashMessage::error("The layout could not be found.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save a layout.")
+        .map_err(e500)?;
+    FlashMessage::info("A new version of the layout has been saved.").send();
+    Ok(see_other(&format!("/admin/layouts/{}", *layout_id)))
+}
+
+#[tracing::instrument(name = "Make a layout the default", skip(pool))]
+pub async fn make_default_layout(
+    layout_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE layouts SET is_default = false WHERE is_default
+        "#
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the default layout.")
+    .map_err(e500)?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE layouts SET is_default = true WHERE layout_id = $1
+        "#,
+        *layout_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to set the default layout.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        // Dropping the transaction keeps the current default.
+        FlashMessage::error("The layout could not be found.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to set the default layout.")
+        .map_err(e500)?;
+    FlashMessage::info("The default layout has been changed.").send();
+    Ok(see_other("/admin/layouts"))
+}
+
+/// Returns `false` if the layout does not exist.
+async fn insert_version(
+    transaction: &mut Transaction<'_, Postgres>,
+    layout_id: Uuid,
+    fields: &LayoutFields,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO layout_versions (
+            layout_version_id, layout_id, version, header, footer, postal_address
+        )
+        SELECT
+            $1,
+            l.layout_id,
+            COALESCE(
+                (SELECT MAX(version) FROM layout_versions WHERE layout_id = l.layout_id),
+                0
+            ) + 1,
+            $3,
+            $4,
+            $5
+        FROM layouts l
+        WHERE l.layout_id = $2
+        "#,
+        Uuid::new_v4(),
+        layout_id,
+        fields.header,
+        fields.footer,
+        fields.postal_address
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(n_inserted == 1)
+}
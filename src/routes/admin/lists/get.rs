@@ -0,0 +1,101 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+pub async fn lists_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows_html = String::new();
+    for list in get_list_summaries(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}{default}</td>
+                <td>{n_confirmed}</td>
+                <td>{n_pending}</td>
+            </tr>"#,
+            name = encode_minimal(&list.name),
+            default = if list.is_default { " (default)" } else { "" },
+            n_confirmed = list.n_confirmed,
+            n_pending = list.n_pending,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Mailing lists</title>
+</head>
+
+<body>
+        {msg_html}
+    <table>
+        <tr>
+            <th>List</th>
+            <th>Confirmed</th>
+            <th>Pending</th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/lists" method="post">
+        <label>Name
+            <input type="text" placeholder="Enter list name" name="name" />
+        </label>
+        <button type="submit">Create list</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+        )))
+}
+
+struct ListSummary {
+    name: String,
+    is_default: bool,
+    n_confirmed: i64,
+    n_pending: i64,
+}
+
+#[tracing::instrument(name = "Get list summaries", skip(pool))]
+async fn get_list_summaries(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.name,
+            l.is_default,
+            COUNT(*) FILTER (WHERE m.status = 'confirmed') as "n_confirmed!",
+            COUNT(*) FILTER (WHERE m.status = 'pending_confirmation') as "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        GROUP BY l.list_id
+        ORDER BY l.is_default DESC, l.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve mailing lists.")?;
+    Ok(lists)
+}
@@ -0,0 +1,43 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(pool, form))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    if name.is_empty() {
+        FlashMessage::error("Please enter a name for the list.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name) VALUES ($1, $2)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to create a mailing list.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error("A list with that name already exists.").send();
+    } else {
+        FlashMessage::info("The list has been created.").send();
+    }
+    Ok(see_other("/admin/lists"))
+}
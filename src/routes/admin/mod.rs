@@ -1,10 +1,22 @@
 mod dashboard;
+mod email_address;
+mod failed_deliveries;
+mod layouts;
+mod lists;
 mod password;
+mod segments;
 mod logout;
 mod newsletter;
+mod subscribers;
 
 
 pub use dashboard::admin_dashboard;
+pub use email_address::{change_email_address, email_address_form};
+pub use failed_deliveries::*;
+pub use layouts::*;
+pub use lists::*;
 pub use password::*;
+pub use segments::*;
 pub use logout::*;
 pub use newsletter::*;
+pub use subscribers::*;
@@ -0,0 +1,43 @@
+use ::actix_web::HttpResponse;
+use actix_web::web;
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveVisibilityFormData {
+    hidden: bool,
+}
+
+#[tracing::instrument(name = "Set archive visibility", skip(pool, form))]
+pub async fn set_archive_visibility(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<ArchiveVisibilityFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET hidden_from_archive = $2
+        WHERE newsletter_issues_id = $1 AND slug IS NOT NULL
+        "#,
+        *issue_id,
+        form.hidden
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to change whether an issue is shown in the archive")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        FlashMessage::error("Only published issues appear in the archive.").send();
+    } else if form.hidden {
+        FlashMessage::info("The issue has been hidden from the archive.").send();
+    } else {
+        FlashMessage::info("The issue is shown in the archive again.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
@@ -0,0 +1,301 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::{
+    get::{layout_select, list_checkboxes, segment_select},
+    post::{
+        check_merge_tags, insert_newsletter_issue, publish_issue, published_message,
+        resolve_issue_layout, resolve_issue_lists, resolve_issue_segment, IssueContent,
+    },
+    schedule::parse_optional_send_at,
+};
+use crate::{
+    authentication::UserId,
+    idempotency::{saved_response, try_processing, IdempotencyKey, NextAction},
+    layouts::get_layouts,
+    mailing_lists::get_lists,
+    segments::get_segments,
+    utils::{e400, e404, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+#[tracing::instrument(name = "Create a draft", skip(pool, form))]
+pub async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftFormData {
+        title,
+        text_content,
+        html_content,
+        markdown_content,
+    } = form.0;
+    let content = IssueContent::new(text_content, html_content, markdown_content);
+    if let Some(e) = check_merge_tags(&pool, &content).await? {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content)
+        .await
+        .context("Failed to store the draft")
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", issue_id)))
+}
+
+pub async fn edit_draft_form(
+    issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(*issue_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Draft not found"))?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let idempotency_key = Uuid::new_v4().to_string();
+    let lists_html = list_checkboxes(&get_lists(&pool).await.map_err(e500)?);
+    let segments_html = segment_select(&get_segments(&pool).await.map_err(e500)?);
+    let layouts_html = layout_select(&get_layouts(&pool).await.map_err(e500)?);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Edit draft</title>
+</head>
+
+<body>
+    {msg_html}
+    <form action="/admin/drafts/{id}" method="post">
+        <label>Title
+            <input type="text" name="title" value="{title}" />
+        </label>
+        <label>Content
+            <textarea name="text_content">{text_content}</textarea>
+        </label>
+        <label>Html
+            <textarea name="html_content">{html_content}</textarea>
+        </label>
+        <label>Markdown (replaces Content and Html when filled in)
+            <textarea name="markdown_content">{markdown_content}</textarea>
+        </label>
+        <button type="submit">Save draft</button>
+        <button type="submit" formaction="/admin/newsletters/test">Send test</button>
+    </form>
+    <p><a href="/admin/newsletters/{id}/preview">Preview</a></p>
+    <form action="/admin/drafts/{id}/publish" method="post">
+        <label>Send at (UTC, leave empty to publish now)
+            <input type="datetime-local" name="send_at" />
+        </label>
+        {lists_html}
+        {segments_html}
+        {layouts_html}
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}" />
+        <button type="submit">Publish</button>
+    </form>
+    <form action="/admin/drafts/{id}/delete" method="post">
+        <button type="submit">Delete draft</button>
+    </form>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            id = *issue_id,
+            title = encode_minimal(&draft.title),
+            text_content = encode_minimal(&draft.text_content),
+            html_content = encode_minimal(&draft.html_content),
+            markdown_content =
+                encode_minimal(draft.markdown_content.as_deref().unwrap_or_default()),
+        )))
+}
+
+#[tracing::instrument(name = "Save a draft", skip(pool, form))]
+pub async fn save_draft(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftFormData {
+        title,
+        text_content,
+        html_content,
+        markdown_content,
+    } = form.0;
+    let content = IssueContent::new(text_content, html_content, markdown_content);
+    if let Some(e) = check_merge_tags(&pool, &content).await? {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&format!("/admin/drafts/{}", *issue_id)));
+    }
+    let n_saved = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            markdown_content = $5,
+            updated_at = now()
+        WHERE newsletter_issues_id = $1 AND status = 'draft'
+        "#,
+        *issue_id,
+        title,
+        content.text_content,
+        content.html_content,
+        content.markdown_content
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to save the draft")
+    .map_err(e500)?
+    .rows_affected();
+    if n_saved == 0 {
+        FlashMessage::error("The issue is not a draft anymore.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", *issue_id)))
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(pool))]
+pub async fn delete_draft(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE newsletter_issues_id = $1 AND status = 'draft'
+        "#,
+        *issue_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the draft")
+    .map_err(e500)?
+    .rows_affected();
+    if n_deleted == 0 {
+        FlashMessage::error("The issue is not a draft anymore.").send();
+    } else {
+        FlashMessage::info("The draft has been deleted.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishFormData {
+    idempotency_key: String,
+    send_at: Option<String>,
+    #[serde(default)]
+    list_ids: Vec<Uuid>,
+    segment_id: Option<Uuid>,
+    layout_id: Option<Uuid>,
+}
+
+#[tracing::instrument(name = "Publish a draft", skip(pool, body, user_id))]
+pub async fn publish_draft(
+    issue_id: web::Path<Uuid>,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let PublishFormData {
+        idempotency_key,
+        send_at,
+        list_ids,
+        segment_id,
+        layout_id,
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let send_at = parse_optional_send_at(send_at.as_deref()).map_err(e400)?;
+    let list_ids = resolve_issue_lists(&pool, &list_ids).await?;
+    let segment_id = resolve_issue_segment(&pool, segment_id).await?;
+    let layout_version_id = resolve_issue_layout(&pool, layout_id)
+        .await?
+        .map(|layout| layout.layout_version_id);
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSaveResponse(http_response) => {
+            FlashMessage::info("The newsletter issue has been published!").send();
+            return Ok(http_response);
+        }
+    };
+    let published = publish_issue(
+        &mut transaction,
+        *issue_id,
+        send_at,
+        &list_ids,
+        segment_id,
+        layout_version_id,
+    )
+    .await
+    .context("Failed to publish the draft")
+    .map_err(e500)?;
+    if !published {
+        // Dropping the transaction also releases the idempotency key.
+        FlashMessage::error("The issue is not a draft anymore.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    published_message(send_at).send();
+    let response = see_other("/admin/newsletters");
+    let response = saved_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    Ok(response)
+}
+
+struct Draft {
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+#[tracing::instrument(name = "Get draft", skip(pool))]
+async fn get_draft(issue_id: Uuid, pool: &PgPool) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT title, text_content, html_content, markdown_content
+        FROM newsletter_issues
+        WHERE newsletter_issues_id = $1 AND status = 'draft'
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a draft.")?;
+    Ok(draft)
+}
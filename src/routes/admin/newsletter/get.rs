@@ -1,14 +1,75 @@
 use ::actix_web::HttpResponse;
-use actix_web::http::header::ContentType;
+use actix_web::{http::header::ContentType, web};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
 
-pub async fn newsletters_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::{
+    layouts::{get_layouts, Layout},
+    mailing_lists::{get_lists, MailingList},
+    segments::{get_segments, Segment},
+    utils::e500,
+};
+
+pub async fn newsletters_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
     let mut error_html = String::new();
     let idempotency_key = uuid::Uuid::new_v4().to_string();
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let mut drafts_html = String::new();
+    for draft in get_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            drafts_html,
+            r#"<li><a href="/admin/drafts/{}">{}</a> (last edited {})</li>"#,
+            draft.newsletter_issues_id,
+            encode_minimal(&draft.title),
+            draft.updated_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+    let mut issues_html = String::new();
+    for issue in get_published_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> ({}, {})</li>"#,
+            issue.newsletter_issues_id,
+            encode_minimal(&issue.title),
+            issue.status,
+            issue.published_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+    let lists_html = list_checkboxes(&get_lists(&pool).await.map_err(e500)?);
+    let segments_html = segment_select(&get_segments(&pool).await.map_err(e500)?);
+    let layouts_html = layout_select(&get_layouts(&pool).await.map_err(e500)?);
+    let mut scheduled_html = String::new();
+    for issue in get_scheduled_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            scheduled_html,
+            r#"<li>
+            <a href="/admin/newsletters/{id}">{title}</a> ({send_at})
+            <form action="/admin/newsletters/{id}/reschedule" method="post">
+                <input type="datetime-local" name="send_at" />
+                <button type="submit">Reschedule</button>
+            </form>
+            <form action="/admin/newsletters/{id}/cancel" method="post">
+                <button type="submit">Cancel</button>
+            </form>
+        </li>"#,
+            id = issue.newsletter_issues_id,
+            title = encode_minimal(&issue.title),
+            send_at = issue.send_at.to_rfc3339()
+        )
+        .unwrap();
+    }
 
     let response = HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -37,10 +98,34 @@ pub async fn newsletters_form(flash_messages: IncomingFlashMessages) -> HttpResp
             <label>Html
             <input type="text" placeholder="Enter Html" name="html_content" />
         </label> 
+        <label>Markdown (replaces Content and Html when filled in)
+            <textarea name="markdown_content"></textarea>
+        </label>
+        <p>Personalise the content with {{{{ name }}}}, {{{{ email }}}}, {{{{ unsubscribe_url }}}} or a subscriber attribute such as {{{{ country }}}}.</p>
+            <label>Send at (UTC, leave empty to publish now)
+            <input type="datetime-local" name="send_at" />
+        </label>
+        {lists_html}
+        {segments_html}
+        {layouts_html}
            <input hidden type="text"   name="idempotency_key" value="{idempotency_key}" />
  
         <button type="submit">submit</button>
+        <button type="submit" formaction="/admin/drafts">Save as draft</button>
+        <button type="submit" formaction="/admin/newsletters/test">Send test</button>
     </form>  
+    <p>Drafts:</p>
+    <ul>
+        {drafts_html}
+    </ul>
+    <p>Scheduled issues:</p>
+    <ul>
+        {scheduled_html}
+    </ul>
+    <p>Published issues:</p>
+    <ul>
+        {issues_html}
+    </ul>
 
 </body>
 
@@ -48,5 +133,129 @@ pub async fn newsletters_form(flash_messages: IncomingFlashMessages) -> HttpResp
         "#
         ));
 
-    response
+    Ok(response)
+}
+
+/// One checkbox per mailing list, with the default list ticked.
+pub(super) fn list_checkboxes(lists: &[MailingList]) -> String {
+    let mut html = String::from("<fieldset><legend>Send to</legend>");
+    for list in lists {
+        writeln!(
+            html,
+            r#"<label><input type="checkbox" name="list_ids" value="{}"{} /> {}</label>"#,
+            list.list_id,
+            if list.is_default { " checked" } else { "" },
+            encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    html.push_str("</fieldset>");
+    html
+}
+
+/// A drop-down of the saved segments; the empty option sends to everyone on
+/// the ticked lists.
+pub(super) fn segment_select(segments: &[Segment]) -> String {
+    let mut html = String::from(
+        r#"<label>Segment <select name="segment_id"><option value="">Everyone</option>"#,
+    );
+    for segment in segments {
+        writeln!(
+            html,
+            r#"<option value="{}">{}</option>"#,
+            segment.segment_id,
+            encode_minimal(&segment.name),
+        )
+        .unwrap();
+    }
+    html.push_str("</select></label>");
+    html
+}
+
+/// A drop-down of the layouts, with the default one selected.
+pub(super) fn layout_select(layouts: &[Layout]) -> String {
+    let mut html =
+        String::from(r#"<label>Layout <select name="layout_id"><option value="">None</option>"#);
+    for layout in layouts {
+        writeln!(
+            html,
+            r#"<option value="{}"{}>{}</option>"#,
+            layout.layout_id,
+            if layout.is_default { " selected" } else { "" },
+            encode_minimal(&layout.name),
+        )
+        .unwrap();
+    }
+    html.push_str("</select></label>");
+    html
+}
+
+struct PublishedIssue {
+    newsletter_issues_id: Uuid,
+    title: String,
+    status: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get published issues", skip(pool))]
+async fn get_published_issues(pool: &PgPool) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT newsletter_issues_id, title, status, published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE status IN ('sending', 'sent')
+        ORDER BY published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve published newsletter issues.")?;
+    Ok(issues)
+}
+
+struct ScheduledIssue {
+    newsletter_issues_id: Uuid,
+    title: String,
+    send_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT newsletter_issues_id, title, send_at as "send_at!"
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY send_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve scheduled newsletter issues.")?;
+    Ok(issues)
+}
+
+struct DraftIssue {
+    newsletter_issues_id: Uuid,
+    title: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftIssue>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        DraftIssue,
+        r#"
+        SELECT newsletter_issues_id, title, updated_at
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY updated_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve drafts.")?;
+    Ok(drafts)
 }
@@ -1,5 +1,17 @@
+mod archive;
+mod draft;
 mod get;
 mod post;
+mod preview;
+mod report;
+mod schedule;
+mod test;
 
+pub use archive::set_archive_visibility;
+pub use draft::{create_draft, delete_draft, edit_draft_form, publish_draft, save_draft};
 pub use get::newsletters_form;
 pub use post::publish_newsletter;
+pub use preview::newsletter_issue_preview;
+pub use report::newsletter_issue_report;
+pub use schedule::{cancel_scheduled_issue, reschedule_issue};
+pub use test::send_test_newsletter;
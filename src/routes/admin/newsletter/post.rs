@@ -1,12 +1,21 @@
+use super::schedule::parse_optional_send_at;
 use crate::{
+    archive::assign_slug,
     authentication::UserId,
     idempotency::{saved_response, try_processing, IdempotencyKey},
+    issue_delivery_worker::enqueue_delivery_tasks,
+    layouts::{resolve_layout_version, LayoutVersion, ResolveLayoutError},
+    mailing_lists::{resolve_list_ids, ResolveListsError},
+    markdown::render_markdown,
+    merge_tags::{validate_merge_tags, MergeTagError},
+    segments::{resolve_segment_id, ResolveSegmentError},
     utils::{e400, e500, see_other},
 };
 use ::actix_web::HttpResponse;
 use actix_web::web;
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
@@ -14,25 +23,56 @@ use uuid::Uuid;
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
+    #[serde(default)]
     text_content: String,
+    #[serde(default)]
     html_content: String,
+    /// When filled in, replaces both hand-written parts.
+    markdown_content: Option<String>,
     idempotency_key: String,
+    /// When set to a future time the issue is scheduled instead of being
+    /// published right away.
+    send_at: Option<String>,
+    /// The lists the issue goes to; the default list when none is ticked.
+    #[serde(default)]
+    list_ids: Vec<Uuid>,
+    /// Narrows the issue down to the subscribers matching a segment.
+    segment_id: Option<Uuid>,
+    /// The layout to send the issue in; its current version is pinned.
+    layout_id: Option<Uuid>,
 }
 
-#[tracing::instrument(name = "Publish a newsletter issue", skip(pool,form),fields(username=tracing::field::Empty,user_id=tracing::field::Empty))]
+#[tracing::instrument(name = "Publish a newsletter issue", skip(pool,body),fields(username=tracing::field::Empty,user_id=tracing::field::Empty))]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    body: web::Bytes,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    // `web::Form` cannot collect the repeated `list_ids` checkboxes.
     let FormData {
         title,
         text_content,
         html_content,
+        markdown_content,
         idempotency_key,
-    } = form.0;
+        send_at,
+        list_ids,
+        segment_id,
+        layout_id,
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let content = IssueContent::new(text_content, html_content, markdown_content);
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let send_at = parse_optional_send_at(send_at.as_deref()).map_err(e400)?;
+    let list_ids = resolve_issue_lists(&pool, &list_ids).await?;
+    let segment_id = resolve_issue_segment(&pool, segment_id).await?;
+    let layout_version_id = resolve_issue_layout(&pool, layout_id)
+        .await?
+        .map(|layout| layout.layout_version_id);
+    if let Some(e) = check_merge_tags(&pool, &content).await? {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
@@ -43,14 +83,21 @@ pub async fn publish_newsletter(
             return Ok(http_response);
         }
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content)
         .await
         .context("Failed to store newsletter issue details")
         .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    publish_issue(
+        &mut transaction,
+        issue_id,
+        send_at,
+        &list_ids,
+        segment_id,
+        layout_version_id,
+    )
+    .await
+    .context("Failed to publish the newsletter issue")
+    .map_err(e500)?;
     // let subscribers = get_confirm_subscribers(&pool).await.map_err(e500)?;
     // for subscriber in subscribers {
     //     match subscriber {
@@ -69,7 +116,7 @@ pub async fn publish_newsletter(
     //         }
     //     }
     // }
-    FlashMessage::info("The newsletter issue has been published!").send();
+    published_message(send_at).send();
     let response = see_other("/admin/newsletters");
     let response = saved_response(transaction, &idempotency_key, *user_id, response)
         .await
@@ -77,6 +124,191 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
+/// Checks the lists ticked in a publish form.
+pub async fn resolve_issue_lists(
+    pool: &PgPool,
+    requested: &[Uuid],
+) -> Result<Vec<Uuid>, actix_web::Error> {
+    resolve_list_ids(pool, requested)
+        .await
+        .map_err(|e| match e {
+            ResolveListsError::UnknownList => e400(e),
+            e => e500(e),
+        })
+}
+
+/// The bodies of an issue as they are stored.
+pub struct IssueContent {
+    pub text_content: String,
+    pub html_content: String,
+    /// The source both parts were rendered from, for issues written in
+    /// Markdown.
+    pub markdown_content: Option<String>,
+}
+
+impl IssueContent {
+    /// A non-blank Markdown body wins over the hand-written parts, which
+    /// are then rendered from it.
+    pub fn new(
+        text_content: String,
+        html_content: String,
+        markdown_content: Option<String>,
+    ) -> Self {
+        match markdown_content.filter(|m| !m.trim().is_empty()) {
+            Some(markdown) => {
+                let rendered = render_markdown(&markdown);
+                Self {
+                    text_content: rendered.text,
+                    html_content: rendered.html,
+                    markdown_content: Some(markdown),
+                }
+            }
+            None => Self {
+                text_content,
+                html_content,
+                markdown_content: None,
+            },
+        }
+    }
+}
+
+/// Checks the merge tags of an issue's bodies, returning the message to show
+/// the author if one of them cannot be filled in.
+pub async fn check_merge_tags(
+    pool: &PgPool,
+    content: &IssueContent,
+) -> Result<Option<String>, actix_web::Error> {
+    match validate_merge_tags(pool, &[&content.text_content, &content.html_content]).await {
+        Ok(()) => Ok(None),
+        Err(MergeTagError::InvalidTag(e)) => Ok(Some(e)),
+        Err(e) => Err(e500(e)),
+    }
+}
+
+/// Checks the layout picked in a publish form and looks up its current
+/// version.
+pub async fn resolve_issue_layout(
+    pool: &PgPool,
+    requested: Option<Uuid>,
+) -> Result<Option<LayoutVersion>, actix_web::Error> {
+    resolve_layout_version(pool, requested)
+        .await
+        .map_err(|e| match e {
+            ResolveLayoutError::UnknownLayout => e400(e),
+            e => e500(e),
+        })
+}
+
+/// Checks the segment picked in a publish form.
+pub async fn resolve_issue_segment(
+    pool: &PgPool,
+    requested: Option<Uuid>,
+) -> Result<Option<Uuid>, actix_web::Error> {
+    resolve_segment_id(pool, requested)
+        .await
+        .map_err(|e| match e {
+            ResolveSegmentError::UnknownSegment => e400(e),
+            e => e500(e),
+        })
+}
+
+/// Takes a draft out of the editor and addresses it to `list_ids`, narrowed
+/// down to `segment_id` if set and wrapped in `layout_version_id` if set: a
+/// future `send_at` schedules it, otherwise its delivery tasks are enqueued
+/// right away. Returns `false` if the issue is not a draft.
+#[tracing::instrument(skip(transaction))]
+pub async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    send_at: Option<DateTime<Utc>>,
+    list_ids: &[Uuid],
+    segment_id: Option<Uuid>,
+    layout_version_id: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let n_published = match send_at {
+        Some(send_at) => sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'scheduled', send_at = $2, updated_at = now()
+            WHERE newsletter_issues_id = $1 AND status = 'draft'
+            "#,
+            issue_id,
+            send_at
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected(),
+        None => sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'sending', published_at = now(), updated_at = now()
+            WHERE newsletter_issues_id = $1 AND status = 'draft'
+            "#,
+            issue_id
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected(),
+    };
+    if n_published == 0 {
+        return Ok(false);
+    }
+    // A draft that was scheduled and then cancelled still has the lists it
+    // was scheduled for; they are replaced by the ones picked now.
+    sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue_lists WHERE newsletter_issues_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to clear the lists of the newsletter issue")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issues_id, list_id)
+        SELECT $1, * FROM UNNEST($2::uuid[])
+        "#,
+        issue_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the lists of the newsletter issue")?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET segment_id = $2, layout_version_id = $3
+        WHERE newsletter_issues_id = $1
+        "#,
+        issue_id,
+        segment_id,
+        layout_version_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the segment and layout of the newsletter issue")?;
+    if send_at.is_none() {
+        assign_slug(transaction, issue_id)
+            .await
+            .context("Failed to give the newsletter issue an archive slug")?;
+        enqueue_delivery_tasks(transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+    Ok(true)
+}
+
+pub fn published_message(send_at: Option<DateTime<Utc>>) -> FlashMessage {
+    match send_at {
+        Some(send_at) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            send_at.to_rfc3339()
+        )),
+        None => FlashMessage::info("The newsletter issue has been published!"),
+    }
+}
+
 // #[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
 // async fn get_confirm_subscribers(
 //     pool: &PgPool,
@@ -100,11 +332,10 @@ pub async fn publish_newsletter(
 // }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &IssueContent,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -114,14 +345,16 @@ async fn insert_newsletter_issue(
                 title,
                 text_content,
                 html_content,
-                published_at
+                markdown_content,
+                status
         )
-        VALUES ($1,$2,$3,$4,now())
+        VALUES ($1,$2,$3,$4,$5,'draft')
         "#,
         newsletter_issue_id,
         title,
-        text_content,
-        html_content
+        content.text_content,
+        content.html_content,
+        content.markdown_content
     )
     .execute(transaction)
     .await?;
@@ -129,29 +362,6 @@ async fn insert_newsletter_issue(
     Ok(newsletter_issue_id)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    nesletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-           INSERT INTO issues_delivery_queue (
-                newsletter_issues_id,
-               subscriber_email
-        )
-        SELECT $1 ,email 
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        nesletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
-
-    Ok(())
-}
-
 // fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
 //     let header_value = headers
 //         .get("Authorization")
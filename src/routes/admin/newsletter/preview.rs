@@ -0,0 +1,77 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    layouts::{get_layout_version, wrap_html, wrap_text},
+    utils::{e404, e500},
+};
+
+/// Shows an issue the way subscribers will receive it, in the layout it was
+/// published with. The HTML part is rendered in a sandboxed frame so that it
+/// cannot script the admin pages.
+pub async fn newsletter_issue_preview(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content, status, layout_version_id
+        FROM newsletter_issues
+        WHERE newsletter_issues_id = $1
+        "#,
+        *issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")
+    .map_err(e500)?
+    .ok_or_else(|| e404("Newsletter issue not found"))?;
+    let layout = match issue.layout_version_id {
+        Some(layout_version_id) => Some(
+            get_layout_version(pool.get_ref(), layout_version_id)
+                .await
+                .context("Failed to retrieve the layout of a newsletter issue.")
+                .map_err(e500)?,
+        ),
+        None => None,
+    };
+    let back = if issue.status == "draft" {
+        format!("/admin/drafts/{}", *issue_id)
+    } else {
+        format!("/admin/newsletters/{}", *issue_id)
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Preview</title>
+</head>
+
+<body>
+    <h1>{title}</h1>
+    <h2>HTML</h2>
+    <iframe sandbox srcdoc="{html_content}" width="100%" height="400"></iframe>
+    <h2>Plain text</h2>
+    <pre>{text_content}</pre>
+    <p><a href="{back}">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            title = encode_minimal(&issue.title),
+            html_content = encode_minimal(&wrap_html(layout.as_ref(), &issue.html_content, None)),
+            text_content = encode_minimal(&wrap_text(layout.as_ref(), &issue.text_content, None)),
+        )))
+}
@@ -0,0 +1,152 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e404, e500};
+
+pub async fn newsletter_issue_report(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let report = get_delivery_report(*issue_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("Newsletter issue not found"))?;
+    let n_enqueued = report.n_sent + report.n_skipped + report.n_pending + report.n_failed;
+    let archive_html = match &report.slug {
+        Some(slug) => format!(
+            r#"<p>Archive: <a href="/archive/{slug}">/archive/{slug}</a>{hidden}</p>
+    <form action="/admin/newsletters/{id}/archive" method="post">
+        <input hidden type="text" name="hidden" value="{hide}" />
+        <button type="submit">{action}</button>
+    </form>"#,
+            slug = slug,
+            hidden = if report.hidden_from_archive {
+                " (hidden)"
+            } else {
+                ""
+            },
+            id = *issue_id,
+            hide = !report.hidden_from_archive,
+            action = if report.hidden_from_archive {
+                "Show in archive"
+            } else {
+                "Hide from archive"
+            },
+        ),
+        None => String::new(),
+    };
+    let format_time =
+        |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339()).unwrap_or_else(|| "-".into());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Delivery report</title>
+</head>
+
+<body>
+    <h1>{title}</h1>
+    <p>Status: {status}</p>
+    <p>Published at: {published_at}</p>
+    {archive_html}
+    <table>
+        <tr><th>Enqueued</th><td>{n_enqueued}</td></tr>
+        <tr><th>Sent</th><td>{n_sent}</td></tr>
+        <tr><th>Pending</th><td>{n_pending}</td></tr>
+        <tr><th>Failed</th><td>{n_failed}</td></tr>
+        <tr><th>Skipped</th><td>{n_skipped}</td></tr>
+        <tr><th>First sent at</th><td>{first_sent_at}</td></tr>
+        <tr><th>Last sent at</th><td>{last_sent_at}</td></tr>
+    </table>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            title = encode_minimal(&report.title),
+            status = report.status,
+            published_at = format_time(report.published_at),
+            n_sent = report.n_sent,
+            n_pending = report.n_pending,
+            n_failed = report.n_failed,
+            n_skipped = report.n_skipped,
+            first_sent_at = format_time(report.first_sent_at),
+            last_sent_at = format_time(report.last_sent_at),
+        )))
+}
+
+struct DeliveryReport {
+    title: String,
+    status: String,
+    published_at: Option<DateTime<Utc>>,
+    slug: Option<String>,
+    hidden_from_archive: bool,
+    n_sent: i64,
+    n_skipped: i64,
+    n_pending: i64,
+    n_failed: i64,
+    first_sent_at: Option<DateTime<Utc>>,
+    last_sent_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get delivery report", skip(pool))]
+async fn get_delivery_report(
+    issue_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<DeliveryReport>, anyhow::Error> {
+    let report = sqlx::query_as!(
+        DeliveryReport,
+        r#"
+        SELECT
+            i.title,
+            i.status,
+            i.published_at,
+            i.slug,
+            i.hidden_from_archive,
+            (
+                SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issues_id = i.newsletter_issues_id AND d.outcome = 'sent'
+            ) as "n_sent!",
+            (
+                SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issues_id = i.newsletter_issues_id AND d.outcome = 'skipped'
+            ) as "n_skipped!",
+            (
+                SELECT COUNT(*) FROM issues_delivery_queue q
+                WHERE q.newsletter_issues_id = i.newsletter_issues_id
+            ) as "n_pending!",
+            (
+                SELECT COUNT(*) FROM failed_deliveries f
+                WHERE f.newsletter_issues_id = i.newsletter_issues_id
+            ) as "n_failed!",
+            (
+                SELECT MIN(d.delivered_at) FROM issue_deliveries d
+                WHERE d.newsletter_issues_id = i.newsletter_issues_id AND d.outcome = 'sent'
+            ) as first_sent_at,
+            (
+                SELECT MAX(d.delivered_at) FROM issue_deliveries d
+                WHERE d.newsletter_issues_id = i.newsletter_issues_id AND d.outcome = 'sent'
+            ) as last_sent_at
+        FROM newsletter_issues i
+        WHERE i.newsletter_issues_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the delivery report of a newsletter issue.")?;
+    Ok(report)
+}
@@ -0,0 +1,118 @@
+use ::actix_web::HttpResponse;
+use actix_web::web;
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e400, e500, see_other};
+
+/// Accepts RFC 3339 timestamps as well as the `YYYY-MM-DDTHH:MM` value of a
+/// `datetime-local` input, which is taken to be UTC.
+fn parse_send_at(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(send_at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(send_at.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+        .map(|send_at| send_at.and_utc())
+        .map_err(|_| format!("{} is not a valid send time.", s))
+}
+
+/// Empty values mean "send now", as do times that have already passed.
+pub fn parse_optional_send_at(send_at: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+    match send_at.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(s) => Ok(Some(parse_send_at(s)?).filter(|send_at| *send_at > Utc::now())),
+    }
+}
+
+#[tracing::instrument(name = "Cancel a scheduled issue", skip(pool))]
+pub async fn cancel_scheduled_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let n_cancelled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'draft', send_at = NULL, updated_at = now()
+        WHERE newsletter_issues_id = $1 AND status = 'scheduled'
+        "#,
+        *issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to cancel a scheduled issue")
+    .map_err(e500)?
+    .rows_affected();
+    // The lists are picked again when the draft is published.
+    sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issue_lists il
+        USING newsletter_issues i
+        WHERE
+            il.newsletter_issues_id = i.newsletter_issues_id
+            AND i.newsletter_issues_id = $1
+            AND i.status = 'draft'
+        "#,
+        *issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the lists of a cancelled issue")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a scheduled issue.")
+        .map_err(e500)?;
+    if n_cancelled == 0 {
+        FlashMessage::error("The issue is not scheduled anymore.").send();
+    } else {
+        FlashMessage::info("The scheduled issue has been moved back to drafts.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    send_at: String,
+}
+
+#[tracing::instrument(name = "Reschedule an issue", skip(pool, form))]
+pub async fn reschedule_issue(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let send_at = parse_send_at(form.send_at.trim()).map_err(e400)?;
+    let n_rescheduled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET send_at = $2
+        WHERE newsletter_issues_id = $1 AND status = 'scheduled'
+        "#,
+        *issue_id,
+        send_at
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule an issue")
+    .map_err(e500)?
+    .rows_affected();
+    if n_rescheduled == 0 {
+        FlashMessage::error("The issue is not scheduled anymore.").send();
+    } else {
+        FlashMessage::info(format!(
+            "The issue has been rescheduled for {}.",
+            send_at.to_rfc3339()
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
@@ -0,0 +1,116 @@
+use ::actix_web::HttpResponse;
+use actix_web::web;
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    layouts::{wrap_html, wrap_text, FooterLinks},
+    link_signer::LinkSigner,
+    merge_tags::MergeValues,
+    routes::{admin::email_address::get_user_email, PREFERENCES_PATH, UNSUBSCRIBE_PATH},
+    utils::{e400, e500, see_other},
+};
+
+use super::post::{check_merge_tags, resolve_issue_layout, IssueContent};
+
+/// Stands in for the subscriber's name in test copies.
+const TEST_NAME: &str = "Test Subscriber";
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    markdown_content: Option<String>,
+    /// Test copies are wrapped in the current version of the layout.
+    layout_id: Option<Uuid>,
+}
+
+/// Sends a single copy of the issue to the logged-in admin. Nothing is
+/// stored: the queue and `newsletter_issues` are left untouched. The copy is
+/// rendered like a subscriber's, with the admin's address, a placeholder
+/// name and links signed for the admin.
+#[tracing::instrument(
+    name = "Send a test newsletter",
+    skip(body, pool, email_client, link_signer)
+)]
+pub async fn send_test_newsletter(
+    body: web::Bytes,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = get_user_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?
+        .and_then(|email| SubscriberEmail::parse(email).ok());
+    let email = match email {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("Set your email address before sending a test copy.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    // Posted from the publish form, whose empty "None" layout option
+    // `web::Form` cannot read as `None`.
+    let FormData {
+        title,
+        text_content,
+        html_content,
+        markdown_content,
+        layout_id,
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let content = IssueContent::new(text_content, html_content, markdown_content);
+    if let Some(e) = check_merge_tags(&pool, &content).await? {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let layout = resolve_issue_layout(&pool, layout_id).await?;
+    let preferences_url = link_signer.signed_url(PREFERENCES_PATH, email.as_ref());
+    let unsubscribe_url = link_signer.signed_url(UNSUBSCRIBE_PATH, email.as_ref());
+    let values = MergeValues {
+        name: TEST_NAME,
+        email: email.as_ref(),
+        unsubscribe_url: &unsubscribe_url,
+        attributes: &serde_json::Value::Null,
+    };
+    let links = FooterLinks {
+        preferences_url: &preferences_url,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let subject = format!("[Test] {}", title);
+    if let Err(e) = email_client
+        .send_email(
+            &email,
+            &subject,
+            &wrap_html(
+                layout.as_ref(),
+                &values.render_html(&content.html_content),
+                Some(&links),
+            ),
+            &wrap_text(
+                layout.as_ref(),
+                &values.render_text(&content.text_content),
+                Some(&links),
+            ),
+        )
+        .await
+    {
+        tracing::error!(
+            error.cause = ?e,
+            error.message = %e,
+            "Failed to send a test copy of a newsletter issue",
+        );
+        FlashMessage::error("The test copy could not be sent.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    FlashMessage::info(format!("A test copy has been sent to {}.", email)).send();
+    Ok(see_other("/admin/newsletters"))
+}
@@ -33,7 +33,7 @@ pub async fn change_password(
     }
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
     let credentials = Credentials {
-        username: username,
+        username,
         password: from.0.current_password,
     };
     if let Err(e) = validate_credentials(credentials, &pool).await {
@@ -42,7 +42,7 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            crate::authentication::AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            crate::authentication::AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
     authentication::change_password(*user_id, from.0.new_password, &pool)
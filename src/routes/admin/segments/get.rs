@@ -0,0 +1,85 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{domain::SubscriberAttributes, segments::get_segments, utils::e500};
+
+pub async fn segments_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows_html = String::new();
+    for segment in get_segments(&pool).await.map_err(e500)? {
+        let day = |at: Option<chrono::DateTime<chrono::Utc>>| {
+            at.map(|at| at.format("%Y-%m-%d").to_string())
+                .unwrap_or_default()
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{attributes}</td>
+                <td>{from}</td>
+                <td>{before}</td>
+                <td>{n_matching}</td>
+            </tr>"#,
+            name = encode_minimal(&segment.name),
+            attributes = encode_minimal(&SubscriberAttributes::describe(&segment.attributes)),
+            from = day(segment.subscribed_from),
+            before = day(segment.subscribed_before),
+            n_matching = segment.n_matching,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Segments</title>
+</head>
+
+<body>
+        {msg_html}
+    <table>
+        <tr>
+            <th>Segment</th>
+            <th>Attributes</th>
+            <th>Signed up from</th>
+            <th>Signed up before</th>
+            <th>Confirmed subscribers</th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/segments" method="post">
+        <label>Name
+            <input type="text" placeholder="Enter segment name" name="name" />
+        </label>
+        <label>Attributes (one name=value per line)
+            <textarea name="attributes"></textarea>
+        </label>
+        <label>Signed up from <input type="date" name="from"></label>
+        <label>to <input type="date" name="to"></label>
+        <button type="submit">Create segment</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+        )))
+}
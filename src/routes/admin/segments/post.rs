@@ -0,0 +1,78 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberAttributes,
+    utils::{e500, see_other, start_of_day},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    /// One `name=value` condition per line.
+    attributes: String,
+    /// First signup day to include, as `YYYY-MM-DD`.
+    from: Option<String>,
+    /// Last signup day to include, as `YYYY-MM-DD`.
+    to: Option<String>,
+}
+
+#[tracing::instrument(name = "Create a segment", skip(pool, form))]
+pub async fn create_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        name,
+        attributes,
+        from,
+        to,
+    } = form.0;
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Please enter a name for the segment.").send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+    let parsed = SubscriberAttributes::parse_lines(&attributes).and_then(|attributes| {
+        let subscribed_from = non_empty(from)
+            .map(|day| start_of_day(&day, 0))
+            .transpose()?;
+        let subscribed_before = non_empty(to).map(|day| start_of_day(&day, 1)).transpose()?;
+        Ok((attributes, subscribed_from, subscribed_before))
+    });
+    let (attributes, subscribed_from, subscribed_before) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, attributes, subscribed_from, subscribed_before)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        serde_json::Value::from(attributes),
+        subscribed_from,
+        subscribed_before
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to create a segment.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error("A segment with that name already exists.").send();
+    } else {
+        FlashMessage::info("The segment has been created.").send();
+    }
+    Ok(see_other("/admin/segments"))
+}
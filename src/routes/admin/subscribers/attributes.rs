@@ -0,0 +1,151 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberAttributes,
+    utils::{e404, e500, see_other},
+};
+
+pub async fn subscriber_attributes_form(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT email, attributes FROM subscriptions WHERE id = $1
+        "#,
+        *subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")
+    .map_err(e500)?
+    .ok_or_else(|| e404("Subscriber not found"))?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows_html = String::new();
+    if let Some(attributes) = subscriber.attributes.as_object() {
+        for (name, value) in attributes {
+            writeln!(
+                rows_html,
+                r#"<tr>
+                <td>{name}</td>
+                <td>{value}</td>
+            </tr>"#,
+                name = encode_minimal(name),
+                value = encode_minimal(value.as_str().unwrap_or_default()),
+            )
+            .unwrap();
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Subscriber attributes</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>Attributes of {email}</p>
+    <table>
+        <tr>
+            <th>Name</th>
+            <th>Value</th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/subscribers/{id}/attributes" method="post">
+        <label>Name
+            <input type="text" placeholder="e.g. country" name="name" />
+        </label>
+        <label>Value (leave empty to remove the attribute)
+            <input type="text" name="value" />
+        </label>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            email = encode_minimal(&subscriber.email),
+            id = *subscriber_id,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributeFormData {
+    name: String,
+    value: String,
+}
+
+#[tracing::instrument(name = "Set a subscriber attribute", skip(form, pool))]
+pub async fn set_subscriber_attribute(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<AttributeFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let location = format!("/admin/subscribers/{}/attributes", *subscriber_id);
+    let name = match SubscriberAttributes::parse_name(&form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let value = form.value.trim();
+    let n_updated = if value.is_empty() {
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions SET attributes = attributes - $2 WHERE id = $1
+            "#,
+            *subscriber_id,
+            name
+        )
+        .execute(pool.get_ref())
+        .await
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions SET attributes = attributes || jsonb_build_object($2::text, $3::text)
+            WHERE id = $1
+            "#,
+            *subscriber_id,
+            name,
+            value
+        )
+        .execute(pool.get_ref())
+        .await
+    }
+    .context("Failed to update the subscriber's attributes.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        FlashMessage::error("The subscriber could not be found.").send();
+        return Ok(see_other("/admin/subscribers"));
+    }
+    if value.is_empty() {
+        FlashMessage::info("The attribute has been removed.").send();
+    } else {
+        FlashMessage::info("The attribute has been saved.").send();
+    }
+    Ok(see_other(&location))
+}
@@ -0,0 +1,180 @@
+use ::actix_web::HttpResponse;
+use actix_web::{
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web,
+};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::utils::{e400, start_of_day};
+
+#[derive(serde::Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    #[serde(default)]
+    format: ExportFormat,
+    status: Option<String>,
+    /// First signup day to include, as `YYYY-MM-DD`.
+    from: Option<String>,
+    /// Last signup day to include, as `YYYY-MM-DD`.
+    to: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: String,
+}
+
+struct Filters {
+    status: Option<String>,
+    subscribed_from: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+}
+
+/// Streams every matching row of `subscriptions` straight from Postgres to
+/// the client; the channel in between holds at most a few rows at a time.
+#[tracing::instrument(name = "Export subscribers", skip_all)]
+pub async fn export_subscribers(
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let parameters = parameters.into_inner();
+    let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+    let filters = Filters {
+        status: non_empty(parameters.status),
+        subscribed_from: non_empty(parameters.from)
+            .map(|day| start_of_day(&day, 0))
+            .transpose()
+            .map_err(e400)?,
+        subscribed_before: non_empty(parameters.to)
+            .map(|day| start_of_day(&day, 1))
+            .transpose()
+            .map_err(e400)?,
+    };
+    let format = parameters.format;
+
+    let (tx, rx) = mpsc::channel(64);
+    let pool = pool.get_ref().clone();
+    tokio::spawn(async move {
+        if let Err(e) = stream_subscribers(&pool, &filters, format, &tx).await {
+            tracing::error!(
+                error.cause = ?e,
+                error.message = %e,
+                "Failed to export subscribers.",
+            );
+            // The client sees the response being cut short.
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+
+    let (content_type, filename) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "subscribers.csv"),
+        ExportFormat::Json => ("application/json", "subscribers.json"),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(content_type.parse().unwrap()))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename.into())],
+        })
+        .streaming(ReceiverStream::new(rx)))
+}
+
+async fn stream_subscribers(
+    pool: &PgPool,
+    filters: &Filters,
+    format: ExportFormat,
+    tx: &mpsc::Sender<Result<web::Bytes, anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    let mut rows = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR status = $1)
+            AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+            AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+        ORDER BY subscribed_at, id
+        "#,
+        filters.status,
+        filters.subscribed_from,
+        filters.subscribed_before,
+    )
+    .fetch(pool);
+
+    let mut first = true;
+    let opening = match format {
+        ExportFormat::Csv => "id,email,name,status,subscribed_at\n",
+        ExportFormat::Json => "[",
+    };
+    send(tx, opening.into()).await?;
+    while let Some(row) = rows.try_next().await? {
+        let subscriber = ExportedSubscriber {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            status: row.status,
+            subscribed_at: row.subscribed_at.to_rfc3339(),
+        };
+        let chunk = match format {
+            ExportFormat::Csv => {
+                let subscriber = ExportedSubscriber {
+                    email: spreadsheet_safe(subscriber.email),
+                    name: spreadsheet_safe(subscriber.name),
+                    ..subscriber
+                };
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(Vec::new());
+                writer.serialize(&subscriber)?;
+                writer.into_inner()?
+            }
+            ExportFormat::Json => {
+                let mut chunk = if first { Vec::new() } else { b",".to_vec() };
+                serde_json::to_writer(&mut chunk, &subscriber)?;
+                chunk
+            }
+        };
+        first = false;
+        send(tx, chunk).await?;
+    }
+    if let ExportFormat::Json = format {
+        send(tx, b"]".to_vec()).await?;
+    }
+    Ok(())
+}
+
+/// Spreadsheets run cells starting with `=`, `+`, `-` or `@` as formulas, and
+/// names and emails are typed in by subscribers: a leading `'` makes them
+/// plain text.
+fn spreadsheet_safe(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value
+    }
+}
+
+async fn send(
+    tx: &mpsc::Sender<Result<web::Bytes, anyhow::Error>>,
+    chunk: Vec<u8>,
+) -> Result<(), anyhow::Error> {
+    tx.send(Ok(chunk.into()))
+        .await
+        .map_err(|_| anyhow::anyhow!("The client went away during the export."))
+}
@@ -0,0 +1,248 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{domain::SubscriberAttributes, utils::e500};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    page: Option<i64>,
+    search: Option<String>,
+}
+
+pub async fn subscribers_list(
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let search = parameters
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    let page = parameters.page.unwrap_or(1).max(1);
+    let (subscribers, n_subscribers) = get_subscribers(&pool, search, page).await.map_err(e500)?;
+    let n_pages = ((n_subscribers + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        let mut actions_html = String::new();
+        if subscriber.status == "pending_confirmation" {
+            writeln!(
+                actions_html,
+                r#"<form action="/admin/subscribers/{}/confirm" method="post">
+                        <button type="submit">Confirm</button>
+                    </form>"#,
+                subscriber.id
+            )
+            .unwrap();
+        }
+        if subscriber.status != "unsubscribed" {
+            writeln!(
+                actions_html,
+                r#"<form action="/admin/subscribers/{}/unsubscribe" method="post">
+                        <button type="submit">Unsubscribe</button>
+                    </form>"#,
+                subscriber.id
+            )
+            .unwrap();
+        }
+        writeln!(
+            actions_html,
+            r#"<a href="/admin/subscribers/{}/attributes">Attributes</a>"#,
+            subscriber.id
+        )
+        .unwrap();
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{}/delete" method="post">
+                        <button type="submit">Delete</button>
+                    </form>"#,
+            subscriber.id
+        )
+        .unwrap();
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{name}</td>
+                <td>{status}</td>
+                <td>{lists}</td>
+                <td>{attributes}</td>
+                <td>{subscribed_at}</td>
+                <td>{actions_html}</td>
+            </tr>"#,
+            email = encode_minimal(&subscriber.email),
+            name = encode_minimal(&subscriber.name),
+            status = subscriber.status,
+            lists = encode_minimal(&subscriber.lists.join(", ")),
+            attributes = encode_minimal(&SubscriberAttributes::describe(&subscriber.attributes)),
+            subscribed_at = subscriber.subscribed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    let search_query = search
+        .map(|s| format!("&search={}", urlencoding::encode(s)))
+        .unwrap_or_default();
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?page={}{}">&lt;- Previous</a> "#,
+            page - 1,
+            encode_minimal(&search_query)
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/subscribers?page={}{}">Next -&gt;</a>"#,
+            page + 1,
+            encode_minimal(&search_query)
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Subscribers</title>
+</head>
+
+<body>
+        {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <input type="text" placeholder="Search by email or name" name="search" value="{search}">
+        <button type="submit">Search</button>
+    </form>
+    <p>{n_subscribers} subscribers</p>
+    <p><a href="/admin/subscribers/import">Import from CSV</a></p>
+    <form action="/admin/subscribers/export" method="get">
+        <select name="format">
+            <option value="csv">CSV</option>
+            <option value="json">JSON</option>
+        </select>
+        <select name="status">
+            <option value="">Any status</option>
+            <option value="pending_confirmation">pending_confirmation</option>
+            <option value="confirmed">confirmed</option>
+            <option value="unsubscribed">unsubscribed</option>
+        </select>
+        <label>Signed up from <input type="date" name="from"></label>
+        <label>to <input type="date" name="to"></label>
+        <button type="submit">Export</button>
+    </form>
+    <table>
+        <tr>
+            <th>Email</th>
+            <th>Name</th>
+            <th>Status</th>
+            <th>Lists</th>
+            <th>Attributes</th>
+            <th>Subscribed at</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            search = encode_minimal(search.unwrap_or_default()),
+        )))
+}
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    /// The lists the subscriber joined, each with its membership status.
+    lists: Vec<String>,
+    attributes: serde_json::Value,
+    subscribed_at: DateTime<Utc>,
+}
+
+/// Returns one page of subscribers matching `search`, together with the
+/// total number of matches.
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(
+    pool: &PgPool,
+    search: Option<&str>,
+    page: i64,
+) -> Result<(Vec<Subscriber>, i64), anyhow::Error> {
+    let pattern = search.map(|s| {
+        let escaped = s
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            id,
+            email,
+            name,
+            status,
+            ARRAY(
+                SELECT l.name || ' (' || m.status || ')'
+                FROM list_memberships m
+                JOIN lists l ON l.list_id = m.list_id
+                WHERE m.subscriber_id = subscriptions.id
+                ORDER BY l.name
+            ) as "lists!",
+            attributes,
+            subscribed_at
+        FROM subscriptions
+        WHERE $1::text IS NULL OR email ILIKE $1 OR name ILIKE $1
+        ORDER BY subscribed_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+        pattern,
+        PAGE_SIZE,
+        (page - 1).saturating_mul(PAGE_SIZE),
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    let n_subscribers = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM subscriptions
+        WHERE $1::text IS NULL OR email ILIKE $1 OR name ILIKE $1
+        "#,
+        pattern,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers.")?
+    .count;
+    Ok((subscribers, n_subscribers))
+}
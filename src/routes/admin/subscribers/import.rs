@@ -0,0 +1,433 @@
+use ::actix_web::HttpResponse;
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
+use actix_web::{http::header::ContentType, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+use uuid::Uuid;
+
+use crate::{
+    domain::{NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName},
+    mailing_lists::{get_lists, resolve_list_ids, ResolveListsError},
+    routes::SubscriberEmailKind,
+    utils::{e500, see_other},
+};
+
+const BATCH_SIZE: usize = 1000;
+
+pub async fn import_subscribers_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<option value="{}">{}</option>"#,
+            list.list_id,
+            encode_minimal(&list.name)
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Import subscribers</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>The CSV file needs a header row with a name and an email column.
+    Any other column is stored as an attribute of the subscriber.</p>
+    <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+        <label>CSV file
+            <input type="file" name="file" accept=".csv,text/csv" />
+        </label>
+        <br>
+        <label>List
+            <select name="list_id">
+                {lists_html}
+            </select>
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="mode" value="send_confirmation" checked />
+            Send each of them a confirmation email
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="mode" value="confirmed" />
+            Mark them as confirmed
+        </label>
+        <br>
+        <button type="submit">Import</button>
+    </form>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+        )))
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ImportMode {
+    /// Imported subscribers start receiving issues right away.
+    Confirmed,
+    /// Imported subscribers are pending until they click the link that the
+    /// delivery workers send them.
+    SendConfirmation,
+}
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    file: Bytes,
+    mode: Text<ImportMode>,
+    /// The list to add the subscribers to; the default list when missing.
+    list_id: Option<Text<Uuid>>,
+}
+
+/// The rows of an uploaded CSV, each tagged with its line number.
+struct ParsedCsv {
+    accepted: Vec<AcceptedRow>,
+    rejected: Vec<RejectedRow>,
+}
+
+struct AcceptedRow {
+    line: u64,
+    subscriber: NewSubscriber,
+    attributes: serde_json::Value,
+}
+
+struct RejectedRow {
+    line: u64,
+    email: String,
+    reason: String,
+}
+
+#[tracing::instrument(name = "Import subscribers", skip_all)]
+pub async fn import_subscribers(
+    MultipartForm(form): MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mode = form.mode.into_inner();
+    let requested_lists: Vec<Uuid> = form.list_id.map(Text::into_inner).into_iter().collect();
+    let list_id = match resolve_list_ids(pool.get_ref(), &requested_lists).await {
+        Ok(list_ids) => list_ids[0],
+        Err(ResolveListsError::UnknownList) => {
+            FlashMessage::error("Unknown mailing list.").send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let ParsedCsv {
+        accepted,
+        mut rejected,
+    } = match parse_csv(&form.file.data) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let mut n_imported = 0;
+    for batch in accepted.chunks(BATCH_SIZE) {
+        let outcome = insert_subscribers(&mut transaction, batch, list_id, mode)
+            .await
+            .map_err(e500)?;
+        n_imported += outcome.imported.len();
+        for row in batch {
+            let email = row.subscriber.email.as_ref();
+            let reason = if outcome.imported.contains(email) {
+                continue;
+            } else if outcome.unsubscribed.contains(email) {
+                "Unsubscribed"
+            } else {
+                "Already in the subscriber list"
+            };
+            rejected.push(RejectedRow {
+                line: row.line,
+                email: email.to_owned(),
+                reason: reason.into(),
+            });
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers.")
+        .map_err(e500)?;
+    rejected.sort_by_key(|row| row.line);
+
+    let mut rows_html = String::new();
+    for row in &rejected {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            row.line,
+            encode_minimal(&row.email),
+            encode_minimal(&row.reason),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Import report</title>
+</head>
+
+<body>
+    <p>{n_imported} rows imported, {n_rejected} rows rejected.</p>
+    <table>
+        <tr>
+            <th>Line</th>
+            <th>Email</th>
+            <th>Reason</th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+
+</body>
+
+</html>
+        "#,
+            n_rejected = rejected.len(),
+        )))
+}
+
+/// Splits the rows of the CSV into valid subscribers and rejected rows. Only
+/// a missing header row fails the import as a whole.
+fn parse_csv(data: &[u8]) -> Result<ParsedCsv, String> {
+    let header_error = "The CSV file needs a header row with a name and an email column.";
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(data);
+    let headers = reader.headers().map_err(|_| header_error)?;
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let (name_column, email_column) = match (column("name"), column("email")) {
+        (Some(name_column), Some(email_column)) => (name_column, email_column),
+        _ => return Err(header_error.into()),
+    };
+    let mut attribute_columns = Vec::new();
+    for (i, header) in headers.iter().enumerate() {
+        if i != name_column && i != email_column {
+            attribute_columns.push((i, SubscriberAttributes::parse_name(header)?));
+        }
+    }
+
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+    let mut seen = HashMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                rejected.push(RejectedRow {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    email: String::new(),
+                    reason: "Malformed row".into(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let email = record.get(email_column).unwrap_or_default().to_owned();
+        let name = record.get(name_column).unwrap_or_default().to_owned();
+        let reject = |reason: String| RejectedRow {
+            line,
+            email: email.clone(),
+            reason,
+        };
+        if let Some(first_line) = seen.get(&email) {
+            rejected.push(reject(format!("Duplicate of line {}", first_line)));
+            continue;
+        }
+        let subscriber = match (
+            SubscriberEmail::parse(email.clone()),
+            SubscriberName::parse(name),
+        ) {
+            (Ok(email), Ok(name)) => NewSubscriber { email, name },
+            (Err(_), _) => {
+                rejected.push(reject("Invalid email address".into()));
+                continue;
+            }
+            (_, Err(_)) => {
+                rejected.push(reject("Invalid name".into()));
+                continue;
+            }
+        };
+        let mut attributes = SubscriberAttributes::default();
+        for (i, name) in &attribute_columns {
+            let value = record.get(*i).unwrap_or_default();
+            if !value.is_empty() {
+                attributes.insert(name, value)?;
+            }
+        }
+        seen.insert(email, line);
+        accepted.push(AcceptedRow {
+            line,
+            subscriber,
+            attributes: attributes.into(),
+        });
+    }
+    Ok(ParsedCsv { accepted, rejected })
+}
+
+/// The emails of a batch that were added to the list, and the ones that were
+/// skipped because their owner unsubscribed.
+struct BatchOutcome {
+    imported: HashSet<String>,
+    unsubscribed: HashSet<String>,
+}
+
+/// Adds a batch of subscribers to the list, creating the ones we do not know
+/// yet and skipping the ones that are already on it or have unsubscribed.
+/// The attributes of the added ones are merged into the stored ones.
+#[tracing::instrument(name = "Insert a batch of subscribers", skip_all, fields(n_rows = batch.len()))]
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch: &[AcceptedRow],
+    list_id: Uuid,
+    mode: ImportMode,
+) -> Result<BatchOutcome, anyhow::Error> {
+    let status = match mode {
+        ImportMode::Confirmed => "confirmed",
+        ImportMode::SendConfirmation => "pending_confirmation",
+    };
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|row| row.subscriber.email.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = batch
+        .iter()
+        .map(|row| row.subscriber.name.as_ref().to_owned())
+        .collect();
+    let attributes: Vec<serde_json::Value> =
+        batch.iter().map(|row| row.attributes.clone()).collect();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT id, email, name, $4, $5
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[]) AS t(id, email, name)
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        &ids,
+        &emails,
+        &names,
+        now,
+        status,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to insert imported subscribers.")?;
+    // An import must not resubscribe someone who opted out.
+    let unsubscribed = sqlx::query!(
+        r#"
+        SELECT email FROM subscriptions
+        WHERE email = ANY($1) AND status = 'unsubscribed'
+        "#,
+        &emails,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to look up unsubscribed addresses.")?;
+    let joined = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+        SELECT id, $2, $3, $4
+        FROM subscriptions
+        WHERE email = ANY($1) AND status <> 'unsubscribed'
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+        RETURNING subscriber_id
+        "#,
+        &emails,
+        list_id,
+        status,
+        now,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to add imported subscribers to the list.")?;
+    let joined_ids: Vec<Uuid> = joined.iter().map(|r| r.subscriber_id).collect();
+    // Known addresses follow the import mode too, unless they are already
+    // confirmed on another list.
+    let inserted = sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET
+            status = CASE WHEN s.status = 'confirmed' THEN s.status ELSE $2 END,
+            attributes = s.attributes || t.attributes
+        FROM UNNEST($3::text[], $4::jsonb[]) AS t(email, attributes)
+        WHERE s.id = ANY($1) AND s.email = t.email AND s.status <> 'unsubscribed'
+        RETURNING s.id, s.email
+        "#,
+        &joined_ids,
+        status,
+        &emails,
+        &attributes,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to update the status of imported subscribers.")?;
+
+    if let ImportMode::SendConfirmation = mode {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_email_queue (subscriber_id, list_id, kind)
+            SELECT *, $2, $3 FROM UNNEST($1::uuid[])
+            ON CONFLICT DO NOTHING
+            "#,
+            &joined_ids,
+            list_id,
+            SubscriberEmailKind::Confirmation.as_str(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to enqueue confirmation emails.")?;
+    }
+    Ok(BatchOutcome {
+        imported: inserted.into_iter().map(|r| r.email).collect(),
+        unsubscribed: unsubscribed.into_iter().map(|r| r.email).collect(),
+    })
+}
@@ -0,0 +1,11 @@
+mod attributes;
+mod export;
+mod get;
+mod import;
+mod post;
+
+pub use attributes::{set_subscriber_attribute, subscriber_attributes_form};
+pub use export::export_subscribers;
+pub use get::subscribers_list;
+pub use import::{import_subscribers, import_subscribers_form};
+pub use post::{confirm_subscriber_manually, delete_subscriber, unsubscribe_subscriber};
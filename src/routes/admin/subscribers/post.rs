@@ -0,0 +1,208 @@
+use ::actix_web::HttpResponse;
+use actix_web::web;
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+/// Confirms a pending subscriber and all of their pending list memberships on
+/// their behalf; any confirmation link still in their inbox stops working.
+/// Subscribers who unsubscribed have to sign up again.
+#[tracing::instrument(name = "Confirm a subscriber manually", skip(pool))]
+pub async fn confirm_subscriber_manually(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to confirm a subscriber.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        FlashMessage::error("Only subscribers waiting for confirmation can be confirmed.").send();
+        return Ok(see_other("/admin/subscribers"));
+    }
+    set_membership_status(
+        &mut transaction,
+        subscriber_id,
+        Some("pending_confirmation"),
+        "confirmed",
+    )
+    .await
+    .map_err(e500)?;
+    delete_subscription_tokens(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been confirmed.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Takes the subscriber off every list and drops the deliveries that are
+/// still queued for them.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let email = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to unsubscribe a subscriber.")
+    .map_err(e500)?
+    .map(|r| r.email);
+    let email = match email {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("The subscriber could not be found.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    set_membership_status(&mut transaction, subscriber_id, None, "unsubscribed")
+        .await
+        .map_err(e500)?;
+    delete_subscription_tokens(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    delete_queued_deliveries(&mut transaction, &email)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    delete_subscription_tokens(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let email = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to delete a subscriber.")
+    .map_err(e500)?
+    .map(|r| r.email);
+    let email = match email {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("The subscriber could not be found.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    delete_queued_deliveries(&mut transaction, &email)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been deleted.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Moves the subscriber's memberships in status `from`, or all of them when
+/// `from` is `None`, to `to`.
+async fn set_membership_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    from: Option<&str>,
+    to: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = $3
+        WHERE subscriber_id = $1 AND ($2::text IS NULL OR status = $2)
+        "#,
+        subscriber_id,
+        from,
+        to
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to update list memberships.")?;
+    Ok(())
+}
+
+async fn delete_subscription_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete subscription tokens.")?;
+    Ok(())
+}
+
+async fn delete_queued_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issues_delivery_queue WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete queued deliveries.")?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    layouts::{get_layout_version, wrap_html},
+    merge_tags::MergeValues,
+    utils::{e404, e500},
+};
+
+struct ArchivedIssue {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn archive(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT slug as "slug!", title, published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE slug IS NOT NULL AND published_at IS NOT NULL AND NOT hidden_from_archive
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the archived issues.")
+    .map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li>{} <a href="/archive/{}">{}</a></li>"#,
+            issue.published_at.format("%Y-%m-%d"),
+            issue.slug,
+            encode_minimal(&issue.title),
+        )
+        .unwrap();
+    }
+    if issues.is_empty() {
+        issues_html.push_str("<li>No issues have been published yet.</li>");
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Past issues</title>
+</head>
+
+<body>
+    <h1>Past issues</h1>
+    <ul>
+        {issues_html}
+    </ul>
+    <p><a href="/">Subscribe</a></p>
+</body>
+
+</html>"#,
+        )))
+}
+
+/// Shows a published issue in the layout it was sent with. Merge tags render
+/// empty, as there is no recipient, and the HTML is sanitised since it was
+/// written for mail clients rather than for our own pages.
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, html_content, published_at as "published_at!", layout_version_id
+        FROM newsletter_issues
+        WHERE slug = $1 AND published_at IS NOT NULL AND NOT hidden_from_archive
+        "#,
+        slug.as_str()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve an archived issue.")
+    .map_err(e500)?
+    .ok_or_else(|| e404("Issue not found"))?;
+    let layout = match issue.layout_version_id {
+        Some(layout_version_id) => Some(
+            get_layout_version(pool.get_ref(), layout_version_id)
+                .await
+                .context("Failed to retrieve the layout of an archived issue.")
+                .map_err(e500)?,
+        ),
+        None => None,
+    };
+    let values = MergeValues {
+        name: "",
+        email: "",
+        unsubscribe_url: "",
+        attributes: &serde_json::Value::Null,
+    };
+    let body = values.render_html(&issue.html_content);
+    let content_html = ammonia::clean(&wrap_html(layout.as_ref(), &body, None));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title}</title>
+</head>
+
+<body>
+    <h1>{title}</h1>
+    <p>Published on {published_on}</p>
+    {content_html}
+    <p><a href="/archive">&lt;- Past issues</a></p>
+</body>
+
+</html>"#,
+            title = encode_minimal(&issue.title),
+            published_on = issue.published_at.format("%Y-%m-%d"),
+        )))
+}
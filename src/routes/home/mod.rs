@@ -1,8 +1,55 @@
-use ::actix_web::{HttpRequest, HttpResponse};
-use actix_web::http::header::ContentType;
+use ::actix_web::HttpResponse;
+use actix_web::{http::header::ContentType, web};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
 
-pub async fn home(_req: HttpRequest) -> HttpResponse {
-    HttpResponse::Ok()
+use crate::{mailing_lists::get_lists, utils::e500};
+
+pub async fn home(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<option value="{}">{}</option>"#,
+            list.list_id,
+            encode_minimal(&list.name)
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Newsletter</title>
+</head>
+
+<body>
+    <p>Welcome to our newsletter!</p>
+    <form action="/subscriptions" method="post">
+        <label>Name
+            <input type="text" placeholder="Enter your name" name="name" />
+        </label>
+        <label>Email
+            <input type="email" placeholder="Enter your email" name="email" />
+        </label>
+        <label>List
+            <select name="list_id">
+                {lists_html}
+            </select>
+        </label>
+        <button type="submit">Subscribe</button>
+    </form>
+    <p><a href="/archive">Past issues</a></p>
+    <p><a href="/subscriptions/data">Download or erase your data</a></p>
+</body>
+
+</html>"#,
+        )))
 }
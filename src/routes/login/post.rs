@@ -27,10 +27,10 @@ pub async fn login(
         username: from.0.username,
         password: from.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             session.renew();
             session
                 .insert_user_id(user_id)
@@ -42,10 +42,10 @@ pub async fn login(
         Err(e) => {
             let e = match e {
                 crate::authentication::AuthError::InvalidCredentials(e) => {
-                    LoginError::AuthError(e.into())
+                    LoginError::AuthError(e)
                 }
                 crate::authentication::AuthError::UnexpectedError(e) => {
-                    LoginError::UnexpectedError(e.into())
+                    LoginError::UnexpectedError(e)
                 }
             };
             Err(login_redirect(e))
@@ -70,7 +70,7 @@ impl std::fmt::Debug for LoginError {
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
-        .insert_header((LOCATION, format!("/login")))
+        .insert_header((LOCATION, "/login"))
         .finish();
     InternalError::from_response(e, response)
 }
@@ -1,13 +1,21 @@
+mod archive;
 mod health_check;
 mod home;
 mod subscriptions;
 mod login;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
 mod admin;
 
+pub use archive::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
 pub use admin::*;
@@ -9,18 +9,27 @@ use uuid::Uuid;
 use crate::{
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
     email_client::EmailClient,
-    startup::ApplicationBaseUrl, utils::error_chain_fmt,
+    layouts::{wrap_html, wrap_text, LayoutVersion},
+    mailing_lists::{resolve_list_ids, ResolveListsError},
+    utils::error_chain_fmt,
 };
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// The list to join; sign-ups without one go to the default list.
+    list_id: Option<Uuid>,
 }
 
+/// How long a subscriber who signs up again for a list they are already on
+/// waits before another "already subscribed" email, so that repeated
+/// sign-ups cannot be used to flood their inbox.
+const ALREADY_SUBSCRIBED_NOTICE_INTERVAL_HOURS: i64 = 24;
+
 #[tracing::instrument(
     name = "Adding as a new  subscriber",
-    skip(form,pool,email_client,base_url),
+    skip(form,pool),
     fields(
         email= %form.email,
         name= %form.name,
@@ -29,32 +38,60 @@ pub struct FormData {
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscriberError> {
+    let requested_lists: Vec<Uuid> = form.list_id.into_iter().collect();
     let new_subscriber: NewSubscriber = form
         .0
         .try_into()
         .map_err(SubscriberError::ValidationTokenError)?;
+    let list_id = resolve_list_ids(pool.get_ref(), &requested_lists)
+        .await
+        .map_err(|e| match e {
+            ResolveListsError::UnknownList => SubscriberError::ValidationTokenError(e.to_string()),
+            e => SubscriberError::UnexpectedError(
+                anyhow::Error::new(e).context("Failed to resolve the mailing list"),
+            ),
+        })?[0];
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection form the pool")?;
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
+    let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction)
         .await
-        .context("Failed to insert new subscriber in the db")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .context("Failed to insert new subscriber in the db")?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => get_existing_subscriber(&mut transaction, &new_subscriber.email)
+            .await
+            .context("Failed to retrieve the existing subscriber")?,
+    };
+    let is_pending = join_list(&mut transaction, subscriber_id, list_id)
         .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
-    send_confirmation_email(
-        new_subscriber,
-        &email_client,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email")?;
+        .context("Failed to add the subscriber to the list")?;
+    // Either way the email is only queued, so the response takes as long and
+    // looks the same whether or not the address is already on the list.
+    if is_pending {
+        enqueue_subscriber_email(
+            &mut transaction,
+            subscriber_id,
+            list_id,
+            SubscriberEmailKind::Confirmation,
+        )
+        .await
+        .context("Failed to queue a confirmation email")?;
+    } else if claim_already_subscribed_notice(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to check when the subscriber was last notified")?
+    {
+        enqueue_subscriber_email(
+            &mut transaction,
+            subscriber_id,
+            list_id,
+            SubscriberEmailKind::AlreadySubscribed,
+        )
+        .await
+        .context("Failed to queue an already subscribed email")?;
+    }
     transaction
         .commit()
         .await
@@ -62,16 +99,92 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Records that the subscriber is being told they are already subscribed,
+/// unless they were told so recently; returns whether to send the email.
+#[tracing::instrument(name = "Claim an already subscribed notice", skip(transaction))]
+async fn claim_already_subscribed_notice(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let cutoff = Utc::now() - chrono::Duration::hours(ALREADY_SUBSCRIBED_NOTICE_INTERVAL_HOURS);
+    let claimed = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET last_notified_at = now()
+        WHERE id = $1 AND (last_notified_at IS NULL OR last_notified_at < $2)
+        "#,
+        subscriber_id,
+        cutoff,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(claimed.rows_affected() > 0)
+}
+
+/// What a row of `subscriber_email_queue` asks the worker to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriberEmailKind {
+    /// A fresh confirmation link for a pending list membership.
+    Confirmation,
+    /// A note to somebody who signed up again for a list they are on.
+    AlreadySubscribed,
+    /// Links to export or erase the subscriber's data; not about any list.
+    DataRequest,
+}
+
+impl SubscriberEmailKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberEmailKind::Confirmation => "confirmation",
+            SubscriberEmailKind::AlreadySubscribed => "already_subscribed",
+            SubscriberEmailKind::DataRequest => "data_request",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "confirmation" => Some(SubscriberEmailKind::Confirmation),
+            "already_subscribed" => Some(SubscriberEmailKind::AlreadySubscribed),
+            "data_request" => Some(SubscriberEmailKind::DataRequest),
+            _ => None,
+        }
+    }
+}
+
+/// Queues an email for the delivery worker. An email of the same kind that
+/// is still waiting in the queue is not queued a second time.
+#[tracing::instrument(name = "Queue an email to a subscriber", skip(transaction))]
+pub async fn enqueue_subscriber_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    kind: SubscriberEmailKind,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_email_queue (subscriber_id, list_id, kind)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        list_id,
+        kind.as_str(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subcriber",
-    skip(new_subscriber, email_client, base_url)
+    skip(email, email_client, base_url, layout)
 )]
 pub async fn send_confirmation_email(
-    new_subscriber: NewSubscriber,
+    email: &SubscriberEmail,
     email_client: &EmailClient,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    layout: Option<&LayoutVersion>,
+) -> Result<(), anyhow::Error> {
     let confrimation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -82,7 +195,34 @@ pub async fn send_confirmation_email(
         confrimation_link
     );
     email_client
-        .send_email(&new_subscriber.email, "Welcome", &html_body, &test_body)
+        .send_email(
+            email,
+            "Welcome",
+            &wrap_html(layout, &html_body, None),
+            &wrap_text(layout, &test_body, None),
+        )
+        .await
+}
+
+#[tracing::instrument(
+    name = "Send an already subscribed email to a subscriber",
+    skip(email, email_client, layout)
+)]
+pub async fn send_already_subscribed_email(
+    email: &SubscriberEmail,
+    email_client: &EmailClient,
+    layout: Option<&LayoutVersion>,
+) -> Result<(), anyhow::Error> {
+    let html_body =
+        "You are already subscribed to our newsletter. <br /> There is nothing else to do.";
+    let text_body = "You are already subscribed to our newsletter.\n  There is nothing else to do.";
+    email_client
+        .send_email(
+            email,
+            "You're already subscribed",
+            &wrap_html(layout, html_body, None),
+            &wrap_text(layout, text_body, None),
+        )
         .await
 }
 
@@ -93,11 +233,12 @@ pub async fn send_confirmation_email(
 pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let inserted = sqlx::query!(
         r#"
             INSERT INTO subscriptions (id, email ,name , subscribed_at,status) VALUES ($1,$2,$3,$4,'pending_confirmation')
+            ON CONFLICT (email) DO NOTHING
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
@@ -110,7 +251,81 @@ pub async fn insert_subscriber(
         tracing::error!("Faild to execute query : {:?}",e);
         e
     })?;
-    Ok(subscriber_id)
+    Ok((inserted.rows_affected() > 0).then_some(subscriber_id))
+}
+
+#[tracing::instrument(name = "Get an existing subscriber", skip(transaction, email))]
+async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Uuid, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions WHERE email = $1 FOR UPDATE
+        "#,
+        email.as_ref(),
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(row.id)
+}
+
+/// Adds the subscriber to the list as `pending_confirmation`, or moves a
+/// repeat sign-up back there and drops its old tokens for the list, so that
+/// only the link in the email we are about to queue is valid. Returns `false`
+/// when the subscriber has already confirmed this list.
+#[tracing::instrument(name = "Join a mailing list", skip(transaction))]
+async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let status = sqlx::query!(
+        r#"
+        SELECT status FROM list_memberships
+        WHERE subscriber_id = $1 AND list_id = $2
+        FOR UPDATE
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .map(|r| r.status);
+    if status.as_deref() == Some("confirmed") {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        VALUES ($1, $2, 'pending_confirmation')
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Somebody who had unsubscribed from everything is pending again.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'pending_confirmation'
+        WHERE id = $1 AND status = 'unsubscribed'
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(true)
 }
 
 #[tracing::instrument(
@@ -120,14 +335,16 @@ pub async fn insert_subscriber(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-            INSERT INTO subscription_tokens (subscription_token,subscriber_id) VALUES ($1,$2 )
+            INSERT INTO subscription_tokens (subscription_token,subscriber_id,list_id) VALUES ($1,$2,$3)
         "#,
         subscription_token,
         subscriber_id,
+        list_id,
     )
     .execute(transaction)
     .await
@@ -138,7 +355,7 @@ pub async fn store_token(
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -1,73 +1,245 @@
 use ::actix_web::HttpResponse;
+use actix_web::http::header::ContentType;
 use actix_web::web;
 use actix_web::ResponseError;
 use anyhow::Context;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::startup::SubscriptionTokenLifetime;
 use crate::utils::error_chain_fmt;
- 
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, token_lifetime)
+)]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
+    token_lifetime: web::Data<SubscriptionTokenLifetime>,
 ) -> Result<HttpResponse, ConfirmError> {
-    let id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    let mut transaction = pool
+        .begin()
         .await
-        .context("查询订阅id失败")?;
+        .context("Failed to acquire a Postgres connection form the pool")?;
+    let (subscriber_id, list_id, created_at) =
+        take_subscription_token(&mut transaction, &parameters.subscription_token)
+            .await
+            .context("查询订阅id失败")?
+            .ok_or_else(|| ConfirmError::UnauthorizedTokenError("没有找到id".into()))?;
 
-    match id {
-        Some(subscriber_id) => {
-            confirm_subscriber(&pool, subscriber_id)
-                .await
-                .context("更新确认状态失败")?;
-        }
-        None => return Err(ConfirmError::UnauthorizedTokenError("没有找到id".into())),
+    if created_at + token_lifetime.0 < Utc::now() {
+        let (email, name) = get_subscriber(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to retrieve the subscriber")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to drop an expired token")?;
+        return Ok(expired_link_page(&email, &name, list_id));
     }
+
+    confirm_subscriber(&mut transaction, subscriber_id, list_id)
+        .await
+        .context("更新确认状态失败")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")?;
     Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(token, pool))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+/// Deletes the token and returns who it belonged to, for which list and when
+/// it was issued, so that a confirmation link only ever works once.
+#[tracing::instrument(name = "Take subscription token", skip(token, transaction))]
+pub async fn take_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
     token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, Uuid, DateTime<Utc>)>, sqlx::Error> {
     let result = sqlx::query!(
         r#"
-           SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1
+           DELETE FROM subscription_tokens WHERE subscription_token = $1
+           RETURNING subscriber_id, list_id, created_at
         "#,
         token
     )
-    .fetch_optional(pool)
+    .fetch_optional(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query:{:?}", e);
         e
     })?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.list_id, r.created_at)))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(transaction))]
+async fn get_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(String, String), sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+           SELECT email, name FROM subscriptions WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok((row.email, row.name))
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
            UPDATE subscriptions SET status = 'confirmed' WHERE id = $1
         "#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query:{:?}", e);
         e
     })?;
+    sqlx::query!(
+        r#"
+           UPDATE list_memberships SET status = 'confirmed'
+           WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query:{:?}", e);
+        e
+    })?;
+    // Links from earlier sign-up attempts must not be usable anymore either.
+    sqlx::query!(
+        r#"
+           DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query:{:?}", e);
+        e
+    })?;
+
+    Ok(())
+}
+
+/// Re-submitting the sign-up form rotates the token and sends a fresh link.
+fn expired_link_page(email: &str, name: &str, list_id: Uuid) -> HttpResponse {
+    HttpResponse::Gone()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Confirmation link expired</title>
+</head>
+
+<body>
+    <p>This confirmation link has expired.</p>
+    <form action="/subscriptions" method="post">
+        <input type="hidden" name="email" value="{email}">
+        <input type="hidden" name="name" value="{name}">
+        <input type="hidden" name="list_id" value="{list_id}">
+        <button type="submit">Send me a new confirmation link</button>
+    </form>
+
+</body>
+
+</html>
+        "#,
+            email = encode_minimal(email),
+            name = encode_minimal(name),
+        ))
+}
 
+/// Drops expired confirmation tokens and the pending list memberships they
+/// were for, then every pending subscriber that has no valid token left, no
+/// confirmation email waiting in the queue, and signed up longer than
+/// `token_lifetime` ago.
+pub async fn delete_stale_pending_subscribers(
+    pool: &PgPool,
+    token_lifetime: chrono::Duration,
+) -> Result<(), anyhow::Error> {
+    let cutoff = Utc::now() - token_lifetime;
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens WHERE created_at < $1
+        "#,
+        cutoff
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM list_memberships m
+        WHERE
+            m.status = 'pending_confirmation'
+            AND m.subscribed_at < $1
+            AND NOT EXISTS (
+                SELECT 1 FROM subscription_tokens t
+                WHERE t.subscriber_id = m.subscriber_id AND t.list_id = m.list_id
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM subscriber_email_queue q
+                WHERE q.subscriber_id = m.subscriber_id AND q.list_id = m.list_id
+            )
+        "#,
+        cutoff
+    )
+    .execute(&mut transaction)
+    .await?;
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions s
+        WHERE
+            s.status = 'pending_confirmation'
+            AND s.subscribed_at < $1
+            AND NOT EXISTS (
+                SELECT 1 FROM subscription_tokens t WHERE t.subscriber_id = s.id
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM subscriber_email_queue q WHERE q.subscriber_id = s.id
+            )
+        "#,
+        cutoff
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::info!(
+        n_deleted = result.rows_affected(),
+        "Purged stale pending subscribers."
+    );
     Ok(())
 }
 
@@ -93,4 +265,3 @@ impl std::fmt::Debug for ConfirmError {
         error_chain_fmt(self, f)
     }
 }
- 
\ No newline at end of file
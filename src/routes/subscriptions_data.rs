@@ -0,0 +1,460 @@
+use ::actix_web::HttpResponse;
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::web;
+use actix_web::ResponseError;
+use anyhow::Context;
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::layouts::{wrap_html, wrap_text, LayoutVersion};
+use crate::link_signer::LinkSigner;
+use crate::utils::error_chain_fmt;
+
+use super::SubscriberEmailKind;
+
+pub const EXPORT_PATH: &str = "/subscriptions/export";
+pub const ERASE_PATH: &str = "/subscriptions/erase";
+
+/// How long the links in a data request email keep working.
+const DATA_LINK_LIFETIME_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataLinkParameters {
+    email: String,
+    expires: i64,
+    token: String,
+}
+
+pub async fn data_request_form() -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Your data</title>
+</head>
+
+<body>
+    <p>Enter the address you subscribed with. We will email it links to download or erase the data we hold about you.</p>
+    <form action="/subscriptions/data" method="post">
+        <label>Email address
+            <input type="email" placeholder="Enter email address" name="email" />
+        </label>
+        <button type="submit">Send me the links</button>
+    </form>
+
+</body>
+
+</html>
+        "#,
+    )
+}
+
+/// Answers the same whether or not the address is on the list; only the
+/// owner of the inbox learns the outcome. The email is queued rather than
+/// sent here, so the answer takes as long either way, and a new one is only
+/// queued once the links in the previous one have expired.
+#[tracing::instrument(name = "Request subscriber data", skip(form, pool), fields(email = %form.email))]
+pub async fn request_subscriber_data(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberDataError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(|_| SubscriberDataError::ValidationError("Invalid email address.".into()))?;
+    let cutoff = Utc::now() - chrono::Duration::hours(DATA_LINK_LIFETIME_HOURS);
+    sqlx::query!(
+        r#"
+        WITH requested AS (
+            UPDATE subscriptions SET data_requested_at = now()
+            WHERE email = $1 AND (data_requested_at IS NULL OR data_requested_at < $2)
+            RETURNING id
+        )
+        INSERT INTO subscriber_email_queue (subscriber_id, kind)
+        SELECT id, $3 FROM requested
+        ON CONFLICT DO NOTHING
+        "#,
+        email.as_ref(),
+        cutoff,
+        SubscriberEmailKind::DataRequest.as_str(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to queue the data links email")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(format!(
+        r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Your data</title>
+</head>
+
+<body>
+    <p>If {email} is on our list, we have sent it an email with links to download or erase your data.</p>
+
+</body>
+
+</html>
+        "#,
+        email = encode_minimal(email.as_ref()),
+    )))
+}
+
+#[tracing::instrument(
+    name = "Send the data links email to a subscriber",
+    skip(email, email_client, link_signer, layout)
+)]
+pub async fn send_data_links_email(
+    email: &SubscriberEmail,
+    email_client: &EmailClient,
+    link_signer: &LinkSigner,
+    layout: Option<&LayoutVersion>,
+) -> Result<(), anyhow::Error> {
+    let expires_at = Utc::now() + chrono::Duration::hours(DATA_LINK_LIFETIME_HOURS);
+    let export_link = link_signer.signed_url_until(EXPORT_PATH, email.as_ref(), expires_at);
+    let erase_link = link_signer.signed_url_until(ERASE_PATH, email.as_ref(), expires_at);
+    let html_body = format!(
+        "You asked about the data we hold about you. The links below work for {} hours.<br />\
+        <a href=\"{}\">Download your data</a><br />\
+        <a href=\"{}\">Erase your data</a>",
+        DATA_LINK_LIFETIME_HOURS,
+        encode_minimal(&export_link),
+        encode_minimal(&erase_link)
+    );
+    let text_body = format!(
+        "You asked about the data we hold about you. The links below work for {} hours.\n\
+        Download your data: {}\n\
+        Erase your data: {}",
+        DATA_LINK_LIFETIME_HOURS, export_link, erase_link
+    );
+    email_client
+        .send_email(
+            email,
+            "Your data",
+            &wrap_html(layout, &html_body, None),
+            &wrap_text(layout, &text_body, None),
+        )
+        .await
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(parameters, pool, link_signer))]
+pub async fn export_subscriber_data(
+    parameters: web::Query<DataLinkParameters>,
+    pool: web::Data<PgPool>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, SubscriberDataError> {
+    verify(&link_signer, EXPORT_PATH, &parameters)?;
+    let email = &parameters.email;
+    let subscription = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at, attributes, email_format
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber")?
+    .ok_or(SubscriberDataError::NoData)?;
+    let tokens = sqlx::query!(
+        r#"
+        SELECT subscription_token, created_at FROM subscription_tokens WHERE subscriber_id = $1
+        "#,
+        subscription.id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscription tokens")?;
+    let list_memberships = sqlx::query!(
+        r#"
+        SELECT l.name, m.status, m.subscribed_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.name
+        "#,
+        subscription.id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the list memberships")?;
+    let queued_deliveries = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id, n_attempts, next_attempt_at
+        FROM issues_delivery_queue
+        WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the queued deliveries")?;
+    let deliveries = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id, outcome, n_attempts, delivered_at
+        FROM issue_deliveries
+        WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the deliveries")?;
+    let failed_deliveries = sqlx::query!(
+        r#"
+        SELECT newsletter_issues_id, n_attempts, last_error, failed_at
+        FROM failed_deliveries
+        WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the failed deliveries")?;
+
+    let export = serde_json::json!({
+        "subscription": {
+            "id": subscription.id,
+            "email": subscription.email,
+            "name": subscription.name,
+            "status": subscription.status,
+            "subscribed_at": subscription.subscribed_at.to_rfc3339(),
+            "attributes": subscription.attributes,
+        },
+        // What the preference center shows: the format issues are sent in
+        // and the lists the subscriber receives.
+        "preferences": {
+            "email_format": subscription.email_format,
+            "lists": list_memberships
+                .iter()
+                .filter(|m| m.status == "confirmed")
+                .map(|m| &m.name)
+                .collect::<Vec<_>>(),
+        },
+        "list_memberships": list_memberships.iter().map(|m| serde_json::json!({
+            "list": m.name,
+            "status": m.status,
+            "subscribed_at": m.subscribed_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+        "subscription_tokens": tokens.iter().map(|t| serde_json::json!({
+            "subscription_token": t.subscription_token,
+            "created_at": t.created_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+        "queued_deliveries": queued_deliveries.iter().map(|d| serde_json::json!({
+            "newsletter_issues_id": d.newsletter_issues_id,
+            "n_attempts": d.n_attempts,
+            "next_attempt_at": d.next_attempt_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+        "deliveries": deliveries.iter().map(|d| serde_json::json!({
+            "newsletter_issues_id": d.newsletter_issues_id,
+            "outcome": d.outcome,
+            "n_attempts": d.n_attempts,
+            "delivered_at": d.delivered_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+        "failed_deliveries": failed_deliveries.iter().map(|d| serde_json::json!({
+            "newsletter_issues_id": d.newsletter_issues_id,
+            "n_attempts": d.n_attempts,
+            "last_error": d.last_error,
+            "failed_at": d.failed_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+    });
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber-data.json".into())],
+        })
+        .json(export))
+}
+
+/// Link scanners follow every URL in an email, so a plain GET only asks for
+/// confirmation; the POST does the erasing.
+#[tracing::instrument(name = "Show the erasure page", skip(parameters, link_signer))]
+pub async fn erase_subscriber_data_form(
+    parameters: web::Query<DataLinkParameters>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, SubscriberDataError> {
+    verify(&link_signer, ERASE_PATH, &parameters)?;
+    let action = format!(
+        "{}?email={}&expires={}&token={}",
+        ERASE_PATH,
+        urlencoding::encode(&parameters.email),
+        parameters.expires,
+        urlencoding::encode(&parameters.token)
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Erase your data</title>
+</head>
+
+<body>
+    <p>Do you want us to forget {email}? This cannot be undone.</p>
+    <form action="{action}" method="post">
+        <button type="submit">Erase my data</button>
+    </form>
+
+</body>
+
+</html>
+        "#,
+            email = encode_minimal(&parameters.email),
+            action = encode_minimal(&action),
+        )))
+}
+
+#[tracing::instrument(name = "Erase subscriber data", skip(parameters, pool, link_signer))]
+pub async fn erase_subscriber_data(
+    parameters: web::Query<DataLinkParameters>,
+    pool: web::Data<PgPool>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, SubscriberDataError> {
+    verify(&link_signer, ERASE_PATH, &parameters)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection form the pool")?;
+    erase_subscriber(&mut transaction, &parameters.email)
+        .await
+        .context("Failed to erase the subscriber")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Data erased</title>
+</head>
+
+<body>
+    <p>Your data has been erased.</p>
+
+</body>
+
+</html>
+        "#,
+    ))
+}
+
+fn verify(
+    link_signer: &LinkSigner,
+    path: &str,
+    parameters: &DataLinkParameters,
+) -> Result<(), SubscriberDataError> {
+    link_signer
+        .verify_until(
+            path,
+            &parameters.email,
+            parameters.expires,
+            &parameters.token,
+        )
+        .map_err(SubscriberDataError::InvalidLink)
+}
+
+/// Deletes the subscriber together with their tokens and pending deliveries.
+/// Past deliveries stay in the issue reports, but under an anonymous address.
+#[tracing::instrument(name = "Erase a subscriber", skip(transaction, email))]
+async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE email = $1)
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscriptions WHERE email = $1
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM issues_delivery_queue WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM failed_deliveries WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET subscriber_email = 'erased-' || gen_random_uuid()
+        WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberDataError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The link is invalid or has expired.")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error("We hold no data about this address.")]
+    NoData,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for SubscriberDataError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            SubscriberDataError::ValidationError(_) => reqwest::StatusCode::BAD_REQUEST,
+            SubscriberDataError::InvalidLink(_) => reqwest::StatusCode::UNAUTHORIZED,
+            SubscriberDataError::NoData => reqwest::StatusCode::NOT_FOUND,
+            SubscriberDataError::UnexpectedError(_) => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for SubscriberDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
@@ -0,0 +1,326 @@
+use ::actix_web::HttpResponse;
+use actix_web::http::header::ContentType;
+use actix_web::web;
+use actix_web::ResponseError;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::domain::SubscriberName;
+use crate::link_signer::LinkSigner;
+use crate::utils::{error_chain_fmt, see_other};
+
+use super::UNSUBSCRIBE_PATH;
+
+/// Every issue links here, signed for its recipient.
+pub const PREFERENCES_PATH: &str = "/subscriptions/preferences";
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    email: String,
+    token: String,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailFormat {
+    Html,
+    PlainText,
+}
+
+impl EmailFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailFormat::Html => "html",
+            EmailFormat::PlainText => "plain_text",
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    name: String,
+    email_format: EmailFormat,
+    /// The lists to receive; the confirmed ones left unticked are
+    /// unsubscribed, while pending sign-ups are left waiting.
+    #[serde(default)]
+    list_ids: Vec<Uuid>,
+}
+
+struct ListChoice {
+    list_id: Uuid,
+    name: String,
+    is_member: bool,
+    is_pending: bool,
+}
+
+#[tracing::instrument(
+    name = "Show the preferences page",
+    skip(parameters, pool, link_signer, flash_messages)
+)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    link_signer: web::Data<LinkSigner>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, PreferencesError> {
+    link_signer
+        .verify(PREFERENCES_PATH, &parameters.email, &parameters.token)
+        .map_err(PreferencesError::InvalidLink)?;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, name, email_format FROM subscriptions WHERE email = $1
+        "#,
+        parameters.email
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber")?
+    .ok_or(PreferencesError::UnknownSubscriber)?;
+    let lists = sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.list_id = l.list_id AND m.subscriber_id = $1 AND m.status = 'confirmed'
+            ) as "is_member!",
+            EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.list_id = l.list_id
+                    AND m.subscriber_id = $1
+                    AND m.status = 'pending_confirmation'
+            ) as "is_pending!"
+        FROM lists l
+        ORDER BY l.is_default DESC, l.name
+        "#,
+        subscriber.id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the mailing lists")?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in &lists {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list_ids" value="{}"{} /> {}{}</label>"#,
+            list.list_id,
+            if list.is_member { " checked" } else { "" },
+            encode_minimal(&list.name),
+            if list.is_pending {
+                " (waiting for you to confirm)"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+    }
+    let checked = |format: EmailFormat| {
+        if subscriber.email_format == format.as_str() {
+            " checked"
+        } else {
+            ""
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Your preferences</title>
+</head>
+
+<body>
+        {msg_html}
+    <p>Preferences for {email}</p>
+    <form action="{action}" method="post">
+        <label>Name
+            <input type="text" name="name" value="{name}" />
+        </label>
+        <fieldset>
+            <legend>Lists</legend>
+            {lists_html}
+        </fieldset>
+        <fieldset>
+            <legend>Format</legend>
+            <label><input type="radio" name="email_format" value="html"{html_checked} /> HTML</label>
+            <label><input type="radio" name="email_format" value="plain_text"{plain_text_checked} /> Plain text only</label>
+        </fieldset>
+        <button type="submit">Save</button>
+    </form>
+    <form action="{unsubscribe_action}" method="post">
+        <button type="submit">Unsubscribe from everything</button>
+    </form>
+
+</body>
+
+</html>
+        "#,
+            email = encode_minimal(&parameters.email),
+            name = encode_minimal(&subscriber.name),
+            action = encode_minimal(&link_signer.signed_path(PREFERENCES_PATH, &parameters.email)),
+            unsubscribe_action =
+                encode_minimal(&link_signer.signed_path(UNSUBSCRIBE_PATH, &parameters.email)),
+            html_checked = checked(EmailFormat::Html),
+            plain_text_checked = checked(EmailFormat::PlainText),
+        )))
+}
+
+/// The signed link proves that the subscriber controls the address, so
+/// ticking a list joins it right away, without a confirmation email.
+#[tracing::instrument(
+    name = "Save subscriber preferences",
+    skip(parameters, body, pool, link_signer)
+)]
+pub async fn save_preferences(
+    parameters: web::Query<PreferencesParameters>,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, PreferencesError> {
+    link_signer
+        .verify(PREFERENCES_PATH, &parameters.email, &parameters.token)
+        .map_err(PreferencesError::InvalidLink)?;
+    // `web::Form` cannot collect the repeated `list_ids` checkboxes.
+    let form: PreferencesFormData = serde_html_form::from_bytes(&body)
+        .map_err(|e| PreferencesError::ValidationError(e.to_string()))?;
+    let name = SubscriberName::parse(form.name).map_err(PreferencesError::ValidationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber_id = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET name = $2, email_format = $3
+        WHERE email = $1
+        RETURNING id
+        "#,
+        parameters.email,
+        name.as_ref(),
+        form.email_format.as_str()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to update the subscriber")?
+    .ok_or(PreferencesError::UnknownSubscriber)?
+    .id;
+    update_memberships(&mut transaction, subscriber_id, &form.list_ids)
+        .await
+        .context("Failed to update the list memberships")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save preferences")?;
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(
+        &link_signer.signed_path(PREFERENCES_PATH, &parameters.email),
+    ))
+}
+
+/// Confirms the memberships of the ticked lists, unsubscribes the confirmed
+/// ones left unticked, and keeps the subscriber's overall status in step.
+/// Sign-ups still waiting for confirmation show up unticked, so leaving
+/// them unticked must not cancel them.
+#[tracing::instrument(name = "Update list memberships", skip(transaction))]
+async fn update_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        SELECT $1, list_id, 'confirmed' FROM lists WHERE list_id = ANY($2)
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = 'confirmed'
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND status = 'confirmed' AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND list_id = ANY($2)
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = CASE
+            WHEN EXISTS (
+                SELECT 1 FROM list_memberships
+                WHERE subscriber_id = $1 AND status = 'confirmed'
+            ) THEN 'confirmed'
+            WHEN status = 'pending_confirmation' AND EXISTS (
+                SELECT 1 FROM list_memberships
+                WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+            ) THEN 'pending_confirmation'
+            ELSE 'unsubscribed'
+        END
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The preferences link is invalid.")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error("We have no subscriber with that email address.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            PreferencesError::ValidationError(_) => reqwest::StatusCode::BAD_REQUEST,
+            PreferencesError::InvalidLink(_) => reqwest::StatusCode::UNAUTHORIZED,
+            PreferencesError::UnknownSubscriber => reqwest::StatusCode::NOT_FOUND,
+            PreferencesError::UnexpectedError(_) => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
@@ -0,0 +1,152 @@
+use ::actix_web::HttpResponse;
+use actix_web::http::header::ContentType;
+use actix_web::web;
+use actix_web::ResponseError;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::link_signer::LinkSigner;
+use crate::utils::error_chain_fmt;
+
+/// Signed unsubscribe links, and the `List-Unsubscribe` header, point here.
+pub const UNSUBSCRIBE_PATH: &str = "/subscriptions/unsubscribe";
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    email: String,
+    token: String,
+}
+
+/// Link scanners follow every URL in an email, so a plain GET only asks for
+/// confirmation; the one-click POST from RFC 8058 does the unsubscribing.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, link_signer))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    link_signer
+        .verify(UNSUBSCRIBE_PATH, &parameters.email, &parameters.token)
+        .map_err(UnsubscribeError::InvalidLink)?;
+    let action = format!(
+        "{}?email={}&token={}",
+        UNSUBSCRIBE_PATH,
+        urlencoding::encode(&parameters.email),
+        urlencoding::encode(&parameters.token)
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Unsubscribe</title>
+</head>
+
+<body>
+    <p>Do you want to stop receiving our newsletter at {email}?</p>
+    <form action="{action}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+
+</body>
+
+</html>
+        "#,
+            email = encode_minimal(&parameters.email),
+            action = encode_minimal(&action),
+        )))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, link_signer))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    link_signer: web::Data<LinkSigner>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    link_signer
+        .verify(UNSUBSCRIBE_PATH, &parameters.email, &parameters.token)
+        .map_err(UnsubscribeError::InvalidLink)?;
+    mark_subscriber_as_unsubscribed(&pool, &parameters.email)
+        .await
+        .context("Failed to unsubscribe a subscriber")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+        <!DOCTYPE html>
+<html lang="en">
+ 
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Unsubscribed</title>
+</head>
+
+<body>
+    <p>You have been unsubscribed.</p>
+
+</body>
+
+</html>
+        "#,
+    ))
+}
+
+/// Also drops the deliveries that are still queued for them.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+async fn mark_subscriber_as_unsubscribed(pool: &PgPool, email: &str) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+           UPDATE subscriptions SET status = 'unsubscribed' WHERE email = $1
+        "#,
+        email
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+           UPDATE list_memberships SET status = 'unsubscribed'
+           WHERE subscriber_id = (SELECT id FROM subscriptions WHERE email = $1)
+        "#,
+        email
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+           DELETE FROM issues_delivery_queue WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            UnsubscribeError::InvalidLink(_) => reqwest::StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
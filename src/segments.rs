@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// A saved filter over subscriber attributes and signup dates. An issue sent
+/// to a segment only reaches the confirmed subscribers of its lists that
+/// have all of the segment's attributes and signed up within its bounds.
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub attributes: serde_json::Value,
+    pub subscribed_from: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+    /// How many confirmed subscribers the segment matches right now.
+    pub n_matching: i64,
+}
+
+#[tracing::instrument(name = "Get segments", skip(pool))]
+pub async fn get_segments(pool: &PgPool) -> Result<Vec<Segment>, sqlx::Error> {
+    sqlx::query_as!(
+        Segment,
+        r#"
+        SELECT
+            seg.segment_id,
+            seg.name,
+            seg.attributes,
+            seg.subscribed_from,
+            seg.subscribed_before,
+            (
+                SELECT COUNT(*)
+                FROM subscriptions s
+                WHERE
+                    s.status = 'confirmed'
+                    AND s.attributes @> seg.attributes
+                    AND (seg.subscribed_from IS NULL OR s.subscribed_at >= seg.subscribed_from)
+                    AND (seg.subscribed_before IS NULL OR s.subscribed_at < seg.subscribed_before)
+            ) as "n_matching!"
+        FROM segments seg
+        ORDER BY seg.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Checks that the requested segment exists; `None` targets everyone on the
+/// issue's lists.
+#[tracing::instrument(name = "Resolve segment", skip(executor))]
+pub async fn resolve_segment_id(
+    executor: impl PgExecutor<'_>,
+    requested: Option<Uuid>,
+) -> Result<Option<Uuid>, ResolveSegmentError> {
+    let segment_id = match requested {
+        Some(segment_id) => segment_id,
+        None => return Ok(None),
+    };
+    sqlx::query!(
+        r#"
+        SELECT segment_id FROM segments WHERE segment_id = $1
+        "#,
+        segment_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(|r| Some(r.segment_id))
+    .ok_or(ResolveSegmentError::UnknownSegment)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveSegmentError {
+    #[error("Unknown segment.")]
+    UnknownSegment,
+    #[error(transparent)]
+    UnexpectedError(#[from] sqlx::Error),
+}
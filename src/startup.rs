@@ -2,11 +2,26 @@ use crate::{
     authentication::reject_anonymous_users,
     configuration::{DatabaseSettings, Settings},
     email_client::EmailClient,
+    link_signer::LinkSigner,
+    rate_limiter::RateLimiter,
     routes::{
-        admin_dashboard, change_password, change_password_from, confirm, health_check, home, login, login_form, logout, newsletters_form, publish_newsletter, subscribe
+        admin_dashboard, archive, archived_issue, cancel_scheduled_issue, change_email_address,
+        change_password, change_password_from, confirm, confirm_subscriber_manually, create_draft,
+        create_layout, create_list, create_segment, data_request_form, delete_draft,
+        delete_subscriber, edit_draft_form, edit_layout_form, email_address_form,
+        erase_subscriber_data, erase_subscriber_data_form, export_subscriber_data,
+        export_subscribers, failed_deliveries, health_check, home, import_subscribers,
+        import_subscribers_form, layouts_page, lists_page, login, login_form, logout,
+        make_default_layout, newsletter_issue_preview, newsletter_issue_report, newsletters_form,
+        preferences_form, publish_draft, publish_newsletter, request_subscriber_data,
+        requeue_all_failed_deliveries, requeue_failed_delivery, reschedule_issue, save_draft,
+        save_layout, save_preferences, segments_page, send_test_newsletter, set_archive_visibility,
+        set_subscriber_attribute, subscribe, subscriber_attributes_form, subscribers_list,
+        unsubscribe, unsubscribe_form, unsubscribe_subscriber,
     },
 };
 use ::actix_web::{web, App, HttpServer};
+use actix_multipart::form::MultipartFormConfig;
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{cookie::Key, dev::Server, middleware::from_fn};
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
@@ -14,22 +29,31 @@ use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramewo
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
     db_poll: PgPool,
     email_client: EmailClient,
+    rate_limiter: RateLimiter,
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>,
+    shutdown_grace_period_seconds: u64,
+    subscription_token_lifetime: chrono::Duration,
 ) -> Result<Server, anyhow::Error> {
     let connection = web::Data::new(db_poll);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let link_signer = web::Data::new(LinkSigner::new(base_url.clone(), hmac_secret.clone()));
     let hmac_secret = web::Data::new(HmacSecret(hmac_secret.clone()));
     let email_client = web::Data::new(email_client);
+    let rate_limiter = web::Data::new(rate_limiter);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let subscription_token_lifetime =
+        web::Data::new(SubscriptionTokenLifetime(subscription_token_lifetime));
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
     let server: actix_web::dev::Server = HttpServer::new(move || {
@@ -44,23 +68,141 @@ async fn run(
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archived_issue))
             .route("/subscriptions/confirm", web::get().to(confirm))
             .route("/subscriptions", web::post().to(subscribe))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(save_preferences),
+            )
+            .route("/subscriptions/data", web::get().to(data_request_form))
+            .route(
+                "/subscriptions/data",
+                web::post().to(request_subscriber_data),
+            )
+            .route(
+                "/subscriptions/export",
+                web::get().to(export_subscriber_data),
+            )
+            .route(
+                "/subscriptions/erase",
+                web::get().to(erase_subscriber_data_form),
+            )
+            .route(
+                "/subscriptions/erase",
+                web::post().to(erase_subscriber_data),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_from))
                     .route("/password", web::post().to(change_password))
+                    .route("/email", web::get().to(email_address_form))
+                    .route("/email", web::post().to(change_email_address))
                     .route("/newsletters", web::post().to(publish_newsletter))
                     .route("/newsletters", web::get().to(newsletters_form))
-                    .route("/logout", web::post().to(logout)),
+                    .route("/newsletters/test", web::post().to(send_test_newsletter))
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(newsletter_issue_report),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/preview",
+                        web::get().to(newsletter_issue_preview),
+                    )
+                    .route("/drafts", web::post().to(create_draft))
+                    .route("/drafts/{issue_id}", web::get().to(edit_draft_form))
+                    .route("/drafts/{issue_id}", web::post().to(save_draft))
+                    .route("/drafts/{issue_id}/delete", web::post().to(delete_draft))
+                    .route("/drafts/{issue_id}/publish", web::post().to(publish_draft))
+                    .route(
+                        "/newsletters/{issue_id}/cancel",
+                        web::post().to(cancel_scheduled_issue),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/reschedule",
+                        web::post().to(reschedule_issue),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/archive",
+                        web::post().to(set_archive_visibility),
+                    )
+                    .route("/logout", web::post().to(logout))
+                    .route("/lists", web::get().to(lists_page))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/segments", web::get().to(segments_page))
+                    .route("/segments", web::post().to(create_segment))
+                    .route("/layouts", web::get().to(layouts_page))
+                    .route("/layouts", web::post().to(create_layout))
+                    .route("/layouts/{layout_id}", web::get().to(edit_layout_form))
+                    .route("/layouts/{layout_id}", web::post().to(save_layout))
+                    .route(
+                        "/layouts/{layout_id}/default",
+                        web::post().to(make_default_layout),
+                    )
+                    .route("/subscribers", web::get().to(subscribers_list))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .service(
+                        web::resource("/subscribers/import")
+                            // Imports of tens of thousands of contacts do
+                            // not fit the default 2 MiB in-memory limit.
+                            .app_data(MultipartFormConfig::default().memory_limit(50 * 1024 * 1024))
+                            .route(web::get().to(import_subscribers_form))
+                            .route(web::post().to(import_subscribers)),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(confirm_subscriber_manually),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(unsubscribe_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/attributes",
+                        web::get().to(subscriber_attributes_form),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/attributes",
+                        web::post().to(set_subscriber_attribute),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route("/failed_deliveries", web::get().to(failed_deliveries))
+                    .route(
+                        "/failed_deliveries/requeue",
+                        web::post().to(requeue_failed_delivery),
+                    )
+                    .route(
+                        "/failed_deliveries/requeue_all",
+                        web::post().to(requeue_all_failed_deliveries),
+                    ),
             )
             .app_data(hmac_secret.clone())
             .app_data(connection.clone())
             .app_data(base_url.clone())
             .app_data(email_client.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(link_signer.clone())
+            .app_data(subscription_token_lifetime.clone())
     })
+    // Shutdown is driven by `Application::run_until_stopped`, which drains
+    // in-flight requests for up to the grace period.
+    .disable_signals()
+    .shutdown_timeout(shutdown_grace_period_seconds)
     .listen(listener)?
     .run();
     Ok(server)
@@ -72,6 +214,16 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(configuration.with_db())
 }
 
+/// Every delivery worker keeps a connection busy with its open transaction
+/// and briefly needs a second one to load the issue it is delivering; the
+/// issue scheduler needs one more.
+pub fn get_worker_connection_pool(configuration: &DatabaseSettings, n_workers: u32) -> PgPool {
+    PgPoolOptions::new()
+        .max_connections(2 * n_workers + 1)
+        .acquire_timeout(std::time::Duration::from_secs(2))
+        .connect_lazy_with(configuration.with_db())
+}
+
 pub struct Application {
     port: u16,
     server: Server,
@@ -79,6 +231,8 @@ pub struct Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct SubscriptionTokenLifetime(pub chrono::Duration);
+
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
@@ -91,31 +245,35 @@ impl Application {
         let listener: TcpListener = TcpListener::bind(address).expect("Faild bind address");
         let port = listener.local_addr().unwrap().port();
         let connection_poll = get_connection_pool(&configuration.database);
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let var_name = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
-        let email_client = var_name;
+        let rate_limiter = configuration.email_client.rate_limiter();
+        let email_client = configuration.email_client.client();
+        let subscription_token_lifetime = configuration.application.subscription_token_lifetime();
         let server = run(
             listener,
             connection_poll,
             email_client,
+            rate_limiter,
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri,
+            configuration.application.shutdown_grace_period_seconds,
+            subscription_token_lifetime,
         )
         .await?;
         Ok(Self { port, server })
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    /// Serves requests until `shutdown` is cancelled, then stops accepting
+    /// connections and waits for in-flight requests to complete.
+    pub async fn run_until_stopped(
+        self,
+        shutdown: CancellationToken,
+    ) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            handle.stop(true).await;
+        });
         self.server.await
     }
 
@@ -1,4 +1,5 @@
 use actix_web::HttpResponse;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use reqwest::header::LOCATION;
 use tokio::task::JoinHandle;
 
@@ -6,7 +7,7 @@ pub fn error_chain_fmt(
     e: &impl std::error::Error,
     f: &mut std::fmt::Formatter<'_>,
 ) -> std::fmt::Result {
-    write!(f, "{}\n", e)?;
+    writeln!(f, "{}", e)?;
     let mut current = e.source();
     while let Some(cause) = current {
         write!(f, "\nCaused by:\n\t{}", cause)?;
@@ -38,6 +39,23 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+pub fn e404<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorNotFound(e)
+}
+
+/// Midnight UTC, `offset_days` days after `day`, given as `YYYY-MM-DD`.
+pub fn start_of_day(day: &str, offset_days: i64) -> Result<DateTime<Utc>, String> {
+    let date = NaiveDate::parse_from_str(day.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date {}, expected YYYY-MM-DD", day))?;
+    let midnight = (date + chrono::Duration::days(offset_days))
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    Ok(Utc.from_utc_datetime(&midnight))
+}
+
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
@@ -1,4 +1,8 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
 
 #[tokio::test]
 async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
@@ -36,3 +40,39 @@ async fn logout_clears_session_state() {
 
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_dashboard_shows_the_remaining_daily_email_budget() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let messages_per_day = app.rate_limiter.messages_per_day();
+
+    // The confirmation email counts against the budget too.
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!(
+        "Email budget remaining today: {} of {messages_per_day}",
+        messages_per_day - 1
+    )));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    app.post_newsletter(&nesletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!(
+        "Email budget remaining today: {} of {messages_per_day}",
+        messages_per_day - 2
+    )));
+}
@@ -0,0 +1,140 @@
+use uuid::Uuid;
+use zero2prod::issue_delivery_worker::enqueue_due_issues;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn publish_newsletter(app: &TestApp, title: &str, html_content: &str) -> Uuid {
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": html_content,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT newsletter_issues_id FROM newsletter_issues ORDER BY published_at DESC LIMIT 1"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issues_id
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_with_a_slug_from_their_title() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app, "Weekly update!", "<p>First</p>").await;
+    publish_newsletter(&app, "Weekly update!", "<p>Second</p>").await;
+    app.post_create_draft(&serde_json::json!({
+        "title": "Unfinished draft",
+        "text_content": "Draft",
+        "html_content": "<p>Draft</p>",
+    }))
+    .await;
+
+    let html_page = app.get_archive_html().await;
+
+    assert!(html_page.contains(r#"<a href="/archive/weekly-update">Weekly update!</a>"#));
+    assert!(html_page.contains(r#"<a href="/archive/weekly-update-2">Weekly update!</a>"#));
+    assert!(!html_page.contains("Unfinished draft"));
+}
+
+#[tokio::test]
+async fn an_archived_issue_shows_its_sanitised_html_content() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(
+        &app,
+        "Release notes",
+        "<p>Hi {{ name }}, read the <a href=\"https://example.com/docs\">docs</a>.</p><script>alert(1)</script>",
+    )
+    .await;
+
+    let response = app.get_archived_issue("release-notes").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Release notes</h1>"));
+    assert!(html_page.contains(&format!(
+        "Published on {}",
+        chrono::Utc::now().format("%Y-%m-%d")
+    )));
+    assert!(html_page.contains("<p>Hi , read the"));
+    assert!(html_page.contains(r#"<a href="https://example.com/docs""#));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[tokio::test]
+async fn hidden_issues_are_left_out_of_the_archive() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app, "Release notes", "<p>Body</p>").await;
+
+    let response = app
+        .post_archive_visibility(&issue_id, &serde_json::json!({"hidden": true}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The issue has been hidden from the archive.</i></p>"));
+    assert!(!app.get_archive_html().await.contains("Release notes"));
+    assert_eq!(
+        app.get_archived_issue("release-notes")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+
+    app.post_archive_visibility(&issue_id, &serde_json::json!({"hidden": false}))
+        .await;
+    assert!(app.get_archive_html().await.contains("Release notes"));
+    assert_eq!(
+        app.get_archived_issue("release-notes")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn scheduled_issues_join_the_archive_once_they_are_sent() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Release notes",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Body</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "send_at": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(!app.get_archive_html().await.contains("Release notes"));
+
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(enqueue_due_issues(&app.db_pool).await.unwrap(), 1);
+
+    assert!(app
+        .get_archive_html()
+        .await
+        .contains(r#"<a href="/archive/release-notes">Release notes</a>"#));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_hide_an_issue() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_archive_visibility(&Uuid::new_v4(), &serde_json::json!({"hidden": true}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,153 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::issue_delivery_worker::update_sending_progress;
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn create_draft(app: &TestApp) -> uuid::Uuid {
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as html</p>",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited_and_previewed_without_being_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = create_draft(&app).await;
+    let html_page = app.get_draft_html(&issue_id).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Draft title""#));
+
+    let response = app
+        .post_save_draft(
+            &issue_id,
+            &serde_json::json!({
+                "title": "Edited title",
+                "text_content": "Edited body as plain text",
+                "html_content": "<p>Edited body as html</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", issue_id));
+
+    let html_page = app.get_newsletter_preview_html(&issue_id).await;
+    assert!(html_page.contains("<h1>Edited title</h1>"));
+    assert!(html_page.contains("<pre>Edited body as plain text</pre>"));
+    assert!(html_page.contains("&lt;p&gt;Edited body as html&lt;/p&gt;"));
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/drafts/{}">Edited title</a>"#,
+        issue_id
+    )));
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_and_marks_it_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = create_draft(&app).await;
+    let publish_body = serde_json::json!({
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "send_at": "",
+    });
+    let response = app.post_publish_draft(&issue_id, &publish_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Submitting the same form again is a no-op.
+    let response = app.post_publish_draft(&issue_id, &publish_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+
+    app.dispatch_all_pending_emails().await;
+    update_sending_progress(&app.db_pool).await.unwrap();
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+}
+
+#[tokio::test]
+async fn published_issues_can_no_longer_be_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let issue_id = create_draft(&app).await;
+    app.post_publish_draft(
+        &issue_id,
+        &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+    )
+    .await;
+
+    let response = app.get_draft(&issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app
+        .post_save_draft(
+            &issue_id,
+            &serde_json::json!({
+                "title": "Edited title",
+                "text_content": "Edited body as plain text",
+                "html_content": "<p>Edited body as html</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The issue is not a draft anymore.</i></p>"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    let response = app.post_delete_draft(&issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The draft has been deleted.</i></p>"));
+    let response = app.get_draft(&issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,125 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str, subscribed_at: &str) {
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, 'Name, with comma', $3::text::timestamptz, $4)",
+        Uuid::new_v4(),
+        email,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn insert_subscribers(app: &TestApp) {
+    insert_subscriber(app, "old@example.com", "confirmed", "2024-01-01T12:00:00Z").await;
+    insert_subscriber(
+        app,
+        "pending@example.com",
+        "pending_confirmation",
+        "2024-02-01T12:00:00Z",
+    )
+    .await;
+    insert_subscriber(app, "new@example.com", "confirmed", "2024-03-01T23:59:00Z").await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_export_subscribers("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_by_default() {
+    let app = spawn_app().await;
+    insert_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_export_subscribers("").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+
+    let body = response.bytes().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_ref());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec!["id", "email", "name", "status", "subscribed_at"]
+    );
+    let records: Vec<_> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(&records[0][1], "old@example.com");
+    assert_eq!(&records[0][2], "Name, with comma");
+    assert_eq!(&records[0][3], "confirmed");
+}
+
+#[tokio::test]
+async fn csv_cells_that_look_like_formulas_are_escaped() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, '+1@example.com', '=HYPERLINK("https://evil.example")', now(), 'confirmed')"#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app.get_export_subscribers("").await;
+    let body = response.bytes().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_ref());
+    let record = reader.records().next().unwrap().unwrap();
+    assert_eq!(&record[1], "'+1@example.com");
+    assert_eq!(&record[2], r#"'=HYPERLINK("https://evil.example")"#);
+
+    let response = app.get_export_subscribers("?format=json").await;
+    let exported: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(exported[0]["name"], r#"=HYPERLINK("https://evil.example")"#);
+}
+
+#[tokio::test]
+async fn the_export_can_be_filtered_by_status_and_signup_date() {
+    let app = spawn_app().await;
+    insert_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .get_export_subscribers("?format=json&status=confirmed&from=2024-01-15&to=2024-03-01")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let exported: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0]["email"], "new@example.com");
+    assert_eq!(exported[0]["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn an_empty_export_is_valid_json() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_export_subscribers("?format=json").await;
+
+    let exported: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert!(exported.is_empty());
+}
+
+#[tokio::test]
+async fn an_invalid_date_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_export_subscribers("?from=yesterday").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
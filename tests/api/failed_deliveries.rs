@@ -0,0 +1,176 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn publish_newsletter(app: &TestApp) {
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    let app = spawn_app().await;
+
+    let response = app.get_failed_deliveries().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn deliveries_that_exhaust_their_retries_are_moved_to_failed_deliveries() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.max_attempts = 1;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let queued = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    let failed = sqlx::query!("SELECT n_attempts, last_error FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery should have been moved to failed_deliveries");
+    assert_eq!(failed.n_attempts, 1);
+    assert!(failed.last_error.contains("500"));
+
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("1 failed deliveries"));
+    assert!(html_page.contains("Newsletter title"));
+}
+
+#[tokio::test]
+async fn a_failed_delivery_can_be_requeued() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.max_attempts = 1;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let failed =
+        sqlx::query!("SELECT newsletter_issues_id, subscriber_email FROM failed_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    let response = app
+        .post_requeue_failed_delivery(&serde_json::json!({
+            "newsletter_issues_id": failed.newsletter_issues_id,
+            "subscriber_email": failed.subscriber_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/failed_deliveries");
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>The delivery has been requeued.</i></p>"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let failed = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failed.count, 0);
+}
+
+#[tokio::test]
+async fn a_requeued_delivery_that_fails_again_keeps_its_earlier_attempts() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.max_attempts = 1;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    app.post_requeue_all_failed_deliveries().await;
+    let queued = sqlx::query!(
+        r#"SELECT n_attempts, last_error, cardinality(attempts) as "n_recorded!" FROM issues_delivery_queue"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.n_attempts, 0);
+    assert_eq!(queued.n_recorded, 1);
+    assert!(queued.last_error.is_some());
+    app.dispatch_all_pending_emails().await;
+
+    let failed = sqlx::query!(
+        r#"SELECT n_attempts, cardinality(attempts) as "n_recorded!" FROM failed_deliveries"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(failed.n_attempts, 1);
+    assert_eq!(failed.n_recorded, 2);
+}
+
+#[tokio::test]
+async fn all_failed_deliveries_can_be_requeued_at_once() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.max_attempts = 1;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let response = app.post_requeue_all_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/admin/failed_deliveries");
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>2 deliveries have been requeued.</i></p>"));
+
+    let queued = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 2);
+}
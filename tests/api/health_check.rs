@@ -7,7 +7,7 @@ async fn health_check_works() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .unwrap();
@@ -15,3 +15,25 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn the_server_stops_accepting_connections_on_shutdown() {
+    let app = spawn_app().await;
+
+    app.shutdown.cancel();
+
+    let mut stopped = false;
+    for _ in 0..50 {
+        // A fresh client each time, so that no pooled connection is reused.
+        let response = reqwest::Client::new()
+            .get(format!("{}/health_check", &app.address))
+            .send()
+            .await;
+        if response.is_err() {
+            stopped = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(stopped, "The server kept serving requests after shutdown");
+}
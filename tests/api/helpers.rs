@@ -1,15 +1,30 @@
 use argon2::{password_hash::SaltString, Argon2, Params, PasswordHasher, Version};
+use fake::{
+    faker::{internet::en::SafeEmail, name::en::Name},
+    Fake,
+};
 use once_cell::sync::Lazy;
 use reqwest::Url;
 
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{
+        get_configuration, DatabaseSettings, DeliverySettings, EmailBackend, Settings,
+    },
     email_client::EmailClient,
     idempotency::delete_all_idempotencys,
-    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    issue_delivery_worker::{
+        try_execute_batch, try_execute_task, try_send_subscriber_email, ExecutionOutcome,
+    },
+    link_signer::LinkSigner,
+    rate_limiter::RateLimiter,
+    routes::PREFERENCES_PATH,
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -39,6 +54,11 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub app_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub rate_limiter: RateLimiter,
+    pub link_signer: LinkSigner,
+    pub delivery_settings: DeliverySettings,
+    pub configuration: Settings,
+    pub shutdown: CancellationToken,
 }
 
 pub struct TestUser {
@@ -56,6 +76,14 @@ impl TestUser {
         }
     }
 
+    pub async fn login(&self, app: &TestApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password,
+        }))
+        .await;
+    }
+
     async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
@@ -81,12 +109,46 @@ impl TestUser {
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
-            {
-                break;
+            let outcome = if self.delivery_settings.batch_size > 1 {
+                try_execute_batch(
+                    &self.db_pool,
+                    &self.email_client,
+                    &self.rate_limiter,
+                    &self.link_signer,
+                    &self.delivery_settings,
+                )
+                .await
+            } else {
+                try_execute_task(
+                    &self.db_pool,
+                    &self.email_client,
+                    &self.rate_limiter,
+                    &self.link_signer,
+                    &self.delivery_settings,
+                )
+                .await
+            };
+            match outcome.unwrap() {
+                ExecutionOutcome::EmptyQueue | ExecutionOutcome::DailyCapReached => break,
+                ExecutionOutcome::TaskCompleted => {}
+            }
+        }
+    }
+
+    pub async fn dispatch_all_pending_subscriber_emails(&self) {
+        loop {
+            let outcome = try_send_subscriber_email(
+                &self.db_pool,
+                &self.email_client,
+                &self.rate_limiter,
+                &self.link_signer,
+                &self.configuration.application.base_url,
+                &self.delivery_settings,
+            )
+            .await;
+            match outcome.unwrap() {
+                ExecutionOutcome::EmptyQueue | ExecutionOutcome::DailyCapReached => break,
+                ExecutionOutcome::TaskCompleted => {}
             }
         }
     }
@@ -97,7 +159,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.app_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -112,7 +174,7 @@ impl TestApp {
 
     pub async fn get_change_passwrod(&self) -> reqwest::Response {
         self.app_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -120,7 +182,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.app_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -131,7 +193,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.app_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -140,7 +202,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.app_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -155,7 +217,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.app_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -164,7 +226,17 @@ impl TestApp {
 
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.app_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_data_request(&self, body: String) -> reqwest::Response {
+        self.app_client
+            .post(format!("{}/subscriptions/data", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -172,12 +244,38 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_preferences(&self, email: &str) -> reqwest::Response {
+        self.app_client
+            .get(format!(
+                "{}{}",
+                &self.address,
+                self.link_signer.signed_path(PREFERENCES_PATH, email)
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences(&self, email: &str, body: String) -> reqwest::Response {
+        self.app_client
+            .post(format!(
+                "{}{}",
+                &self.address,
+                self.link_signer.signed_path(PREFERENCES_PATH, email)
+            ))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletter<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.app_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .form(&body)
             .send()
             .await
@@ -186,7 +284,7 @@ impl TestApp {
 
     pub async fn get_newsletter(&self) -> reqwest::Response {
         self.app_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -196,6 +294,448 @@ impl TestApp {
         self.get_newsletter().await.text().await.unwrap()
     }
 
+    pub async fn get_newsletter_report(&self, issue_id: &uuid::Uuid) -> reqwest::Response {
+        self.app_client
+            .get(format!("{}/admin/newsletters/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_archive_visibility<Body>(
+        &self,
+        issue_id: &uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!(
+                "{}/admin/newsletters/{}/archive",
+                &self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_html(&self) -> String {
+        self.app_client
+            .get(format!("{}/archive", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_archived_issue(&self, slug: &str) -> reqwest::Response {
+        self.app_client
+            .get(format!("{}/archive/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel_scheduled_issue(&self, issue_id: &uuid::Uuid) -> reqwest::Response {
+        self.app_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reschedule_issue<Body>(
+        &self,
+        issue_id: &uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!(
+                "{}/admin/newsletters/{}/reschedule",
+                &self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft(&self, issue_id: &uuid::Uuid) -> reqwest::Response {
+        self.app_client
+            .get(format!("{}/admin/drafts/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_html(&self, issue_id: &uuid::Uuid) -> String {
+        self.get_draft(issue_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_save_draft<Body>(
+        &self,
+        issue_id: &uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/drafts/{}", &self.address, issue_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_draft(&self, issue_id: &uuid::Uuid) -> reqwest::Response {
+        self.app_client
+            .post(format!(
+                "{}/admin/drafts/{}/delete",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_publish_draft<Body>(
+        &self,
+        issue_id: &uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!(
+                "{}/admin/drafts/{}/publish",
+                &self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_preview_html(&self, issue_id: &uuid::Uuid) -> String {
+        self.app_client
+            .get(format!(
+                "{}/admin/newsletters/{}/preview",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_email_address_html(&self) -> String {
+        self.app_client
+            .get(format!("{}/admin/email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_email_address<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/email", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_send_test_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/newsletters/test", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_failed_deliveries(&self) -> reqwest::Response {
+        self.app_client
+            .get(format!("{}/admin/failed_deliveries", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.get_failed_deliveries().await.text().await.unwrap()
+    }
+
+    pub async fn post_requeue_failed_delivery<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/failed_deliveries/requeue", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_requeue_all_failed_deliveries(&self) -> reqwest::Response {
+        self.app_client
+            .post(format!(
+                "{}/admin/failed_deliveries/requeue_all",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.app_client
+            .get(format!("{}/admin/subscribers{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: &uuid::Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.app_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.app_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_list<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_layouts_html(&self) -> String {
+        self.app_client
+            .get(format!("{}/admin/layouts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_layout<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/layouts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_layout_html(&self, layout_id: &uuid::Uuid) -> String {
+        self.app_client
+            .get(format!("{}/admin/layouts/{}", &self.address, layout_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_save_layout<Body>(
+        &self,
+        layout_id: &uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/layouts/{}", &self.address, layout_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_make_default_layout(&self, layout_id: &uuid::Uuid) -> reqwest::Response {
+        self.app_client
+            .post(format!(
+                "{}/admin/layouts/{}/default",
+                &self.address, layout_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.app_client
+            .get(format!("{}/admin/segments", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_segment<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!("{}/admin/segments", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_attributes_html(&self, subscriber_id: &uuid::Uuid) -> String {
+        self.app_client
+            .get(format!(
+                "{}/admin/subscribers/{}/attributes",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_attribute<Body>(
+        &self,
+        subscriber_id: &uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.app_client
+            .post(format!(
+                "{}/admin/subscribers/{}/attributes",
+                &self.address, subscriber_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_export_subscribers(&self, query: &str) -> reqwest::Response {
+        self.app_client
+            .get(format!(
+                "{}/admin/subscribers/export{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_import_subscribers_html(&self) -> String {
+        self.app_client
+            .get(format!("{}/admin/subscribers/import", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_import_subscribers(&self, csv: &str, mode: &str) -> reqwest::Response {
+        self.post_import_form(import_form(csv, mode)).await
+    }
+
+    pub async fn post_import_subscribers_to_list(
+        &self,
+        csv: &str,
+        mode: &str,
+        list_id: &uuid::Uuid,
+    ) -> reqwest::Response {
+        self.post_import_form(import_form(csv, mode).text("list_id", list_id.to_string()))
+            .await
+    }
+
+    async fn post_import_form(&self, form: reqwest::multipart::Form) -> reqwest::Response {
+        self.app_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         let get_link = |s: &str| {
@@ -210,8 +750,8 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["TextBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["TextBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
@@ -229,6 +769,18 @@ impl TestApp {
     // }
 }
 
+fn import_form(csv: &str, mode: &str) -> reqwest::multipart::Form {
+    reqwest::multipart::Form::new()
+        .part(
+            "file",
+            reqwest::multipart::Part::text(csv.to_owned())
+                .file_name("subscribers.csv")
+                .mime_str("text/csv")
+                .unwrap(),
+        )
+        .text("mode", mode.to_owned())
+}
+
 pub async fn spawn_app() -> TestApp {
     Lazy::force(&TRACING);
 
@@ -236,13 +788,15 @@ pub async fn spawn_app() -> TestApp {
     let email_server = MockServer::start().await;
     config.database.database_name = Uuid::new_v4().to_string();
     config.application.port = 0;
+    config.email_client.backend = EmailBackend::Postmark;
     config.email_client.base_url = email_server.uri();
     configure_database(&config.database).await;
     let application = Application::build(config.clone()).await.unwrap();
 
     let address = format!("http://127.0.0.1:{}", application.port());
     let port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    let shutdown = CancellationToken::new();
+    tokio::spawn(application.run_until_stopped(shutdown.clone()));
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
         .cookie_store(true)
@@ -254,7 +808,15 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         port,
         test_user: TestUser::generate(),
-        email_client: config.email_client.client(),
+        rate_limiter: config.email_client.rate_limiter(),
+        link_signer: LinkSigner::new(
+            config.application.base_url.clone(),
+            config.application.hmac_secret.clone(),
+        ),
+        email_client: config.email_client.clone().client(),
+        delivery_settings: config.delivery.clone(),
+        configuration: config,
+        shutdown,
         app_client: client,
     };
     test_app.test_user.store(&test_app.db_pool).await;
@@ -295,6 +857,46 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
     db_pool
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name":name,
+        "email":email,
+    }))
+    .unwrap();
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_subscriber_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
@@ -0,0 +1,177 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_import_subscribers("name,email\nUrsula,ursula@example.com\n", "confirmed")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn imported_rows_are_validated_and_deduplicated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers("name,email\nLe Guin,le_guin@example.com\n", "confirmed")
+        .await;
+
+    let csv = "\
+Email,Name
+ursula@example.com,Ursula
+not-an-email,Broken
+ursula@example.com,Ursula again
+le_guin@example.com,Le Guin
+octavia@example.com,
+";
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("1 rows imported, 4 rows rejected."));
+    assert!(html_page.contains("Invalid email address"));
+    assert!(html_page.contains("Duplicate of line 2"));
+    assert!(html_page.contains("Already in the subscriber list"));
+    assert!(html_page.contains("Invalid name"));
+
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[1].email, "ursula@example.com");
+    assert_eq!(saved[1].status, "confirmed");
+}
+
+#[tokio::test]
+async fn imports_do_not_resubscribe_people_who_unsubscribed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers("name,email\nLe Guin,le_guin@example.com\n", "confirmed")
+        .await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE list_memberships SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_create_list(&serde_json::json!({ "name": "Releases" }))
+        .await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists WHERE name = 'Releases'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    let response = app
+        .post_import_subscribers_to_list(
+            "name,email\nLe Guin,le_guin@example.com\n",
+            "confirmed",
+            &list_id,
+        )
+        .await;
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("0 rows imported, 1 rows rejected."));
+    assert!(html_page.contains("Unsubscribed"));
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "unsubscribed");
+    let n_memberships = sqlx::query!(
+        "SELECT COUNT(*) as \"count!\" FROM list_memberships WHERE list_id = $1",
+        list_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_memberships, 0);
+}
+
+#[tokio::test]
+async fn a_csv_without_name_and_email_columns_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_import_subscribers(
+            "full_name,address\nUrsula,ursula@example.com\n",
+            "confirmed",
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains(
+        "<p><i>The CSV file needs a header row with a name and an email column.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_sent_a_confirmation_email_through_the_queue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "name,email\nUrsula,ursula@example.com\nOctavia,octavia@example.com\n";
+    let html_page = app
+        .post_import_subscribers(csv, "send_confirmation")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("2 rows imported, 0 rows rejected."));
+
+    // Nothing goes out until the delivery workers pick the emails up.
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    app.dispatch_all_pending_subscriber_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions ORDER BY status")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved[0].status, "confirmed");
+    assert_eq!(saved[1].status, "pending_confirmation");
+    let n_queued = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriber_email_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
@@ -0,0 +1,204 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn create_layout(app: &TestApp, name: &str, footer: &str) -> Uuid {
+    let response = app
+        .post_create_layout(&serde_json::json!({
+            "name": name,
+            "header": "# Weekly digest",
+            "footer": footer,
+            "postal_address": "1 Main St\nSpringfield",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/layouts");
+    sqlx::query!("SELECT layout_id FROM layouts WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .layout_id
+}
+
+async fn publish_newsletter(app: &TestApp, title: &str, layout_id: Uuid) {
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "layout_id": layout_id.to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_layouts() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_create_layout(&serde_json::json!({
+            "name": "Weekly",
+            "header": "",
+            "footer": "",
+            "postal_address": "",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn layout_names_must_be_unique() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_layout(&app, "Weekly", "Thanks for reading!").await;
+
+    let response = app
+        .post_create_layout(&serde_json::json!({
+            "name": "Weekly",
+            "header": "",
+            "footer": "",
+            "postal_address": "",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let html_page = app.get_layouts_html().await;
+    assert!(html_page.contains("<p><i>A layout with that name already exists.</i></p>"));
+}
+
+#[tokio::test]
+async fn saving_a_layout_adds_a_version() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let layout_id = create_layout(&app, "Weekly", "Thanks for reading!").await;
+
+    let response = app
+        .post_save_layout(
+            &layout_id,
+            &serde_json::json!({
+                "header": "# Weekly digest",
+                "footer": "See you next week!",
+                "postal_address": "1 Main St",
+            }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, &format!("/admin/layouts/{}", layout_id));
+    let html_page = app.get_layout_html(&layout_id).await;
+    assert!(html_page.contains("<p><i>A new version of the layout has been saved.</i></p>"));
+    assert!(html_page.contains("Weekly, version 2"));
+    assert!(html_page.contains("See you next week!"));
+}
+
+#[tokio::test]
+async fn issues_keep_the_layout_version_they_were_published_with() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let layout_id = create_layout(&app, "Weekly", "Thanks for reading!").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app, "First issue", layout_id).await;
+    app.post_save_layout(
+        &layout_id,
+        &serde_json::json!({
+            "header": "# Weekly digest",
+            "footer": "See you next week!",
+            "postal_address": "1 Main St\nSpringfield",
+        }),
+    )
+    .await;
+    publish_newsletter(&app, "Second issue", layout_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    let emails = sent_emails(&app).await;
+    let email = |title: &str| {
+        emails
+            .iter()
+            .find(|email| email["Subject"] == title)
+            .unwrap()
+            .clone()
+    };
+    let first = email("First issue");
+    let html_body = first["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>Weekly digest</h1>\n<p>Newsletter body as HTML</p>"));
+    assert!(html_body.contains("Thanks for reading!"));
+    assert!(html_body.ends_with("<p>1 Main St<br />Springfield</p>"));
+    let text_body = first["TextBody"].as_str().unwrap();
+    assert!(text_body
+        .starts_with("Weekly digest\n\nNewsletter body as plain text\n\nThanks for reading!"));
+    let second = email("Second issue");
+    assert!(second["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("See you next week!"));
+}
+
+#[tokio::test]
+async fn the_default_layout_wraps_confirmation_emails() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let layout_id = create_layout(&app, "Weekly", "Thanks for reading!").await;
+    let response = app.post_make_default_layout(&layout_id).await;
+    assert_is_redirect_to(&response, "/admin/layouts");
+    assert!(app
+        .get_layouts_html()
+        .await
+        .contains("Weekly</a> (default)"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_subscriber_emails().await;
+
+    let email = sent_emails(&app).await.pop().unwrap();
+    let html_body = email["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>Weekly digest</h1>"));
+    assert!(html_body.contains("to confirm your subscription"));
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .ends_with("1 Main St\nSpringfield"));
+}
+
+#[tokio::test]
+async fn publishing_with_an_unknown_layout_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "layout_id": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
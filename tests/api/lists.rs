@@ -0,0 +1,158 @@
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+}
+
+async fn create_list(app: &TestApp, name: &str) -> Uuid {
+    let response = app
+        .post_create_list(&serde_json::json!({ "name": name }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+async fn subscribe_and_confirm(app: &TestApp, email: &str, list_id: Uuid) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}&list_id={}", email, list_id);
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_subscriber_emails().await;
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_create_list(&serde_json::json!({ "name": "Releases" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_lists_show_up_next_to_the_default_list() {
+    let app = spawn_app().await;
+    login(&app).await;
+
+    create_list(&app, "Releases").await;
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The list has been created.</i></p>"));
+    assert!(html_page.contains("Newsletter (default)"));
+    assert!(html_page.contains("Releases"));
+}
+
+#[tokio::test]
+async fn list_names_must_be_unique() {
+    let app = spawn_app().await;
+    login(&app).await;
+    create_list(&app, "Releases").await;
+
+    let response = app
+        .post_create_list(&serde_json::json!({ "name": "Releases" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>A list with that name already exists.</i></p>"));
+}
+
+#[tokio::test]
+async fn confirming_a_subscription_only_confirms_the_chosen_list() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let list_id = create_list(&app, "Releases").await;
+
+    subscribe_and_confirm(&app, "ursula_le_guin%40gmail.com", list_id).await;
+
+    let memberships = sqlx::query!(
+        "SELECT l.name, m.status FROM list_memberships m JOIN lists l USING (list_id)"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships[0].name, "Releases");
+    assert_eq!(memberships[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        Uuid::new_v4()
+    );
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_members_of_the_chosen_lists() {
+    let app = spawn_app().await;
+    login(&app).await;
+    let list_id = create_list(&app, "Releases").await;
+    // One subscriber on the default list, one on "Releases".
+    create_confirmed_subscriber(&app).await;
+    subscribe_and_confirm(&app, "ursula_le_guin%40gmail.com", list_id).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "list_ids": list_id.to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let recipients = sqlx::query!("SELECT subscriber_email FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(recipients.len(), 1);
+    assert_eq!(recipients[0].subscriber_email, "ursula_le_guin@gmail.com");
+}
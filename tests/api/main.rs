@@ -5,4 +5,20 @@ mod subscriptions;
 mod subscriptions_confirm;
 mod login;
 mod admin_dashboard;
-mod change_password;
\ No newline at end of file
+mod change_password;
+mod failed_deliveries;
+mod drafts;
+mod send_test;
+mod unsubscribe;
+mod subscribers;
+mod import_subscribers;
+mod export_subscribers;
+mod subscriber_data;
+mod lists;
+mod preferences;
+mod segments;
+mod merge_tags;
+mod markdown;
+mod layouts;
+mod archive;
+mod rate_limiter;
@@ -0,0 +1,172 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app};
+
+const MARKDOWN: &str = "\
+# Release notes
+
+Read the [docs](https://example.com/docs).<script>alert(1)</script>
+";
+
+#[tokio::test]
+async fn a_markdown_body_is_rendered_into_both_parts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": MARKDOWN,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let issue =
+        sqlx::query!("SELECT text_content, html_content, markdown_content FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(issue.markdown_content.as_deref(), Some(MARKDOWN));
+    assert!(issue.html_content.contains("<h1>Release notes</h1>"));
+    assert!(issue
+        .html_content
+        .contains(r#"<a href="https://example.com/docs""#));
+    assert!(!issue.html_content.contains("<script"));
+    assert_eq!(
+        issue.text_content,
+        "Release notes\n\nRead the docs (https://example.com/docs)."
+    );
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<h1>Release notes</h1>"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Release notes\n\nRead the docs"));
+}
+
+#[tokio::test]
+async fn merge_tags_in_markdown_links_are_rendered_per_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "[Unsubscribe]({{unsubscribe_url}})",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(r#"<a href="http"#));
+    assert!(html_body.contains("/subscriptions/unsubscribe?"));
+    assert!(!html_body.contains("unsubscribe_url"));
+}
+
+#[tokio::test]
+async fn markdown_drafts_keep_their_source_for_editing() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "",
+            "html_content": "",
+            "markdown_content": "Hello *world*",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let issue_id: Uuid = sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issues_id;
+    let html_page = app.get_draft_html(&issue_id).await;
+    assert!(html_page.contains(r#"<textarea name="markdown_content">Hello *world*</textarea>"#));
+
+    let response = app
+        .post_save_draft(
+            &issue_id,
+            &serde_json::json!({
+                "title": "Draft title",
+                "text_content": "Hello world",
+                "html_content": "<p>Hello <em>world</em></p>",
+                "markdown_content": "Hello **everyone**",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", issue_id));
+
+    let draft = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(draft.text_content, "Hello everyone");
+    assert_eq!(
+        draft.html_content,
+        "<p>Hello <strong>everyone</strong></p>\n"
+    );
+}
+
+#[tokio::test]
+async fn clearing_the_markdown_body_keeps_the_hand_written_parts() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_create_draft(&serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Plain",
+        "html_content": "<p>Html</p>",
+        "markdown_content": "  ",
+    }))
+    .await;
+
+    let draft =
+        sqlx::query!("SELECT text_content, html_content, markdown_content FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(draft.text_content, "Plain");
+    assert_eq!(draft.html_content, "<p>Html</p>");
+    assert_eq!(draft.markdown_content, None);
+}
@@ -0,0 +1,106 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn import_subscriber(app: &TestApp) {
+    let csv = "\
+name,email,country
+Ursula,ursula@example.com,<France>
+";
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+fn newsletter_request_body(text_content: &str, html_content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": text_content,
+        "html_content": html_content,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn merge_tags_are_filled_in_for_each_recipient() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&newsletter_request_body(
+            "Hi {{ name }} <{{email}}> from {{ country }}",
+            "<p>Hi {{ name }} from {{ country }}</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Hi Ursula <ursula@example.com> from <France>"));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<p>Hi Ursula from &lt;France&gt;</p>"));
+    assert!(html_body.contains("<a href=\"http://127.0.0.1/subscriptions/unsubscribe?"));
+    assert!(!html_body.contains("{{"));
+}
+
+#[tokio::test]
+async fn unknown_merge_tags_are_rejected_on_publish() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscriber(&app).await;
+
+    let response = app
+        .post_newsletter(&newsletter_request_body(
+            "Hi {{ nickname }}",
+            "<p>Hi {{ name }}</p>",
+        ))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>Unknown merge tag {{ nickname }}.</i></p>"));
+    assert_eq!(n_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn unclosed_merge_tags_are_rejected_when_saving_a_draft() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ name",
+            "html_content": "<p>Hi</p>",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("but never closed.</i></p>"));
+    assert_eq!(n_issues(&app).await, 0);
+}
@@ -1,13 +1,17 @@
-use fake::{
-    faker::{internet::en::SafeEmail, name::en::Name},
-    Fake,
-};
 use wiremock::{
     matchers::{any, method, path},
-    Mock, ResponseTemplate,
+    Mock, MockBuilder, ResponseTemplate,
+};
+
+use zero2prod::{
+    issue_delivery_worker::{enqueue_due_issues, run_worker_until_stopped},
+    rate_limiter::RateLimiter,
 };
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    TestApp,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -97,45 +101,6 @@ async fn newsletters_returns_400_for_invalid_data() {
     }
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name":name,
-        "email":email,
-    }))
-    .unwrap();
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(&app).await;
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 async fn newsletter_creation_is_idempotent() {
     let app = spawn_app().await;
@@ -290,6 +255,548 @@ async fn concurrent_from_submission_is_handled_gracefully() {
 
 // }
 
-// fn when_sending_an_email() -> MockBuilder {
-//     Mock::given(path("/email")).and(method("POST"))
-// }
+#[tokio::test]
+async fn failed_deliveries_are_rescheduled_and_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let login_body = serde_json::json!({
+        "username":&app.test_user.username,
+        "password":&app.test_user.password,
+    });
+    app.post_login(&login_body).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let task =
+        sqlx::query!("SELECT n_attempts, last_error, next_attempt_at FROM issues_delivery_queue",)
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("The failed delivery should still be queued");
+    assert_eq!(task.n_attempts, 1);
+    assert!(task.last_error.is_some());
+    assert!(task.next_attempt_at > chrono::Utc::now());
+
+    sqlx::query!("UPDATE issues_delivery_queue SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Delivery retry")
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let remaining = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.count, 0);
+}
+
+#[tokio::test]
+async fn the_delivery_report_tracks_the_progress_of_an_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue = sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        "/admin/newsletters/{}",
+        issue.newsletter_issues_id
+    )));
+    let html_page = app
+        .get_newsletter_report(&issue.newsletter_issues_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<tr><th>Enqueued</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Sent</th><td>0</td></tr>"));
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app
+        .get_newsletter_report(&issue.newsletter_issues_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<tr><th>Enqueued</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending</th><td>0</td></tr>"));
+    assert!(html_page.contains("<tr><th>Sent</th><td>2</td></tr>"));
+    assert!(!html_page.contains("<tr><th>First sent at</th><td>-</td></tr>"));
+}
+
+#[tokio::test]
+async fn the_delivery_report_of_an_unknown_issue_returns_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_newsletter_report(&uuid::Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletters_can_be_delivered_in_batches() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.batch_size = 10;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK"},
+            {"ErrorCode": 406, "Message": "Inactive recipient"},
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let sent =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_deliveries WHERE outcome = 'sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 1);
+    let retried = sqlx::query!("SELECT n_attempts, last_error FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The rejected message should have been rescheduled");
+    assert_eq!(retried.n_attempts, 1);
+    assert!(retried.last_error.unwrap().contains("Inactive recipient"));
+}
+
+#[tokio::test]
+async fn batches_are_split_to_fit_the_per_second_rate() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.batch_size = 10;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.rate_limiter = RateLimiter::new(2, 1000);
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &wiremock::Request| {
+            let batch: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = batch
+                .iter()
+                .map(|_| serde_json::json!({"ErrorCode": 0, "Message": "OK"}))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        })
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let batch_sizes: Vec<_> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/email/batch")
+        .map(|r| {
+            serde_json::from_slice::<Vec<serde_json::Value>>(&r.body)
+                .unwrap()
+                .len()
+        })
+        .collect();
+    assert_eq!(batch_sizes, vec![2, 1]);
+}
+
+#[tokio::test]
+async fn concurrent_workers_deliver_each_email_exactly_once() {
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(100)))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    tokio::join!(
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails(),
+    );
+
+    let sent =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_deliveries WHERE outcome = 'sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 4);
+}
+
+#[tokio::test]
+async fn deliveries_over_the_daily_cap_stay_queued() {
+    let mut app = spawn_app().await;
+    // Room for the two confirmation emails and a single delivery.
+    app.rate_limiter = RateLimiter::new(50, 3);
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let queued = sqlx::query!("SELECT n_attempts, last_error FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery over the cap should still be queued");
+    assert_eq!(queued.n_attempts, 0);
+    assert!(queued.last_error.is_none());
+}
+
+async fn n_sent_today(app: &TestApp) -> i32 {
+    sqlx::query!("SELECT n_sent FROM email_send_budget")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n_sent
+}
+
+#[tokio::test]
+async fn failed_deliveries_do_not_count_against_the_daily_cap() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let n_confirmations = n_sent_today(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(n_sent_today(&app).await, n_confirmations);
+}
+
+#[tokio::test]
+async fn only_the_accepted_messages_of_a_batch_count_against_the_daily_cap() {
+    let mut app = spawn_app().await;
+    app.delivery_settings.batch_size = 10;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let n_confirmations = n_sent_today(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK"},
+            {"ErrorCode": 406, "Message": "Inactive recipient"},
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(n_sent_today(&app).await, n_confirmations + 1);
+}
+
+#[tokio::test]
+async fn the_worker_finishes_the_in_flight_delivery_on_shutdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        app.shutdown.clone(),
+    ));
+    // Let the worker pick up the task before asking it to stop.
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    app.shutdown.cancel();
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop")
+        .unwrap()
+        .unwrap();
+    let sent =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_deliveries WHERE outcome = 'sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 1);
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_delivered_once_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let send_at = chrono::Utc::now() + chrono::Duration::hours(1);
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "send_at": send_at.to_rfc3339(),
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+    assert!(html_page.contains("/reschedule"));
+
+    assert_eq!(enqueue_due_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+
+    let issue = sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post_reschedule_issue(
+            &issue.newsletter_issues_id,
+            &serde_json::json!({"send_at": "2000-01-01T08:00"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    assert_eq!(enqueue_due_issues(&app.db_pool).await.unwrap(), 1);
+    app.dispatch_all_pending_emails().await;
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sending");
+    assert!(issue.published_at.is_some());
+}
+
+#[tokio::test]
+async fn cancelled_scheduled_newsletters_are_never_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "send_at": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+    });
+    app.post_newsletter(&nesletter_request_body).await;
+    let issue = sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_cancel_scheduled_issue(&issue.newsletter_issues_id)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The scheduled issue has been moved back to drafts.</i></p>"));
+
+    let response = app
+        .post_reschedule_issue(
+            &issue.newsletter_issues_id,
+            &serde_json::json!({"send_at": "2000-01-01T08:00"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The issue is not scheduled anymore.</i></p>"));
+
+    assert_eq!(enqueue_due_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_cancelled_scheduled_newsletter_can_be_published_later() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "send_at": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+    });
+    app.post_newsletter(&nesletter_request_body).await;
+    let issue = sqlx::query!("SELECT newsletter_issues_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_cancel_scheduled_issue(&issue.newsletter_issues_id)
+        .await;
+
+    let response = app
+        .post_publish_draft(
+            &issue.newsletter_issues_id,
+            &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn an_invalid_send_at_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let nesletter_request_body = serde_json::json!({
+        "title":"Newsletter title",
+        "text_content": "Newsletter bodu as plain text",
+        "html_content": "<p>Newsletter bodu as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "send_at": "tomorrow morning",
+    });
+    let response = app.post_newsletter(&nesletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+fn when_sending_an_email() -> MockBuilder {
+    Mock::given(path("/email")).and(method("POST"))
+}
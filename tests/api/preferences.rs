@@ -0,0 +1,221 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+async fn default_list_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    app.test_user.login(app).await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as html</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn the_preferences_page_rejects_a_forged_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    let response = app
+        .app_client
+        .get(format!(
+            "{}/subscriptions/preferences?email={}&token=deadbeef",
+            &app.address,
+            urlencoding::encode(&email)
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_preferences() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    let response = app.get_preferences(&email).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"value="html" checked"#));
+    assert!(html_page.contains(&format!(
+        r#"value="{}" checked /> Newsletter"#,
+        default_list_id(&app).await
+    )));
+    assert!(html_page.contains("Unsubscribe from everything"));
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_and_format() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    let body = format!(
+        "name=Ursula&email_format=plain_text&list_ids={}",
+        default_list_id(&app).await
+    );
+    let response = app.post_preferences(&email, body).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let html_page = app.get_preferences(&email).await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+    let saved = sqlx::query!("SELECT name, email_format, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.email_format, "plain_text");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    let response = app
+        .post_preferences(&email, "name=%20&email_format=html".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn plain_text_subscribers_get_no_html_part() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let body = format!(
+        "name=Ursula&email_format=plain_text&list_ids={}",
+        default_list_id(&app).await
+    );
+    app.post_preferences(&email, body).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert!(body.get("HtmlBody").is_none());
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Manage your preferences: http://127.0.0.1/subscriptions/preferences?"));
+}
+
+#[tokio::test]
+async fn leaving_a_list_skips_issues_already_queued_for_it() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    publish_newsletter(&app).await;
+
+    // No list ticked.
+    app.post_preferences(&email, "name=Ursula&email_format=html".into())
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT outcome FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.outcome, "skipped");
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn saving_preferences_keeps_sign_ups_waiting_for_confirmation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let releases_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name) VALUES ($1, 'Releases')",
+        releases_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        SELECT id, $1, 'pending_confirmation' FROM subscriptions
+        "#,
+        releases_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let html_page = app.get_preferences(&email).await.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"value="{}" /> Releases (waiting for you to confirm)"#,
+        releases_id
+    )));
+    let body = format!(
+        "name=Ursula&email_format=html&list_ids={}",
+        default_list_id(&app).await
+    );
+    app.post_preferences(&email, body).await;
+
+    let status = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE list_id = $1",
+        releases_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status;
+    assert_eq!(status, "pending_confirmation");
+}
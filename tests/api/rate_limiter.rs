@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+use zero2prod::rate_limiter::RateLimiter;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn current_window(app: &TestApp) -> chrono::DateTime<chrono::Utc> {
+    sqlx::query!("SELECT window_start FROM email_send_rate")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .window_start
+}
+
+#[tokio::test]
+async fn acquire_does_not_wait_while_the_budget_has_room() {
+    let app = spawn_app().await;
+    let rate_limiter = RateLimiter::new(10, 1000);
+    let start = Instant::now();
+
+    let granted = rate_limiter.acquire(&app.db_pool, 10).await.unwrap();
+
+    assert_eq!(granted, 10);
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn acquire_never_grants_more_than_the_per_second_rate() {
+    let app = spawn_app().await;
+    let rate_limiter = RateLimiter::new(10, 1000);
+
+    let granted = rate_limiter.acquire(&app.db_pool, 25).await.unwrap();
+
+    assert_eq!(granted, 10);
+}
+
+#[tokio::test]
+async fn the_per_second_budget_is_shared_by_every_worker() {
+    let app = spawn_app().await;
+    // Two limiters stand in for two worker processes.
+    let first = RateLimiter::new(10, 1000);
+    let second = RateLimiter::new(10, 1000);
+    first.acquire(&app.db_pool, 10).await.unwrap();
+    let used_up_window = current_window(&app).await;
+
+    let granted = second.acquire(&app.db_pool, 1).await.unwrap();
+
+    assert_eq!(granted, 1);
+    assert!(current_window(&app).await > used_up_window);
+}
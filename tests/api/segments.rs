@@ -0,0 +1,227 @@
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn import_subscribers(app: &TestApp) {
+    let csv = "\
+name,email,country,plan
+Ursula,ursula@example.com,fr,pro
+Octavia,octavia@example.com,us,pro
+";
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_a_segment() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_create_segment(&serde_json::json!({
+            "name": "French",
+            "attributes": "country=fr",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn imported_columns_become_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    import_subscribers(&app).await;
+
+    let saved =
+        sqlx::query!("SELECT attributes FROM subscriptions WHERE email = 'ursula@example.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({ "country": "fr", "plan": "pro" })
+    );
+}
+
+#[tokio::test]
+async fn attributes_can_be_set_and_removed_from_the_admin_ui() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_subscriber_attribute(
+            &subscriber_id,
+            &serde_json::json!({ "name": "language", "value": "Français" }),
+        )
+        .await;
+    let location = format!("/admin/subscribers/{}/attributes", subscriber_id);
+    assert_is_redirect_to(&response, &location);
+    let html_page = app.get_subscriber_attributes_html(&subscriber_id).await;
+    assert!(html_page.contains("<p><i>The attribute has been saved.</i></p>"));
+    assert!(html_page.contains("Français"));
+
+    let response = app
+        .post_subscriber_attribute(
+            &subscriber_id,
+            &serde_json::json!({ "name": "plan", "value": "" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &location);
+    let html_page = app.get_subscriber_attributes_html(&subscriber_id).await;
+    assert!(html_page.contains("<p><i>The attribute has been removed.</i></p>"));
+    let saved = sqlx::query!(
+        "SELECT attributes FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({ "country": "fr", "language": "Français" })
+    );
+}
+
+#[tokio::test]
+async fn invalid_attribute_names_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+
+    app.post_subscriber_attribute(
+        &subscriber_id,
+        &serde_json::json!({ "name": "first name", "value": "Ursula" }),
+    )
+    .await;
+
+    let html_page = app.get_subscriber_attributes_html(&subscriber_id).await;
+    assert!(!html_page.contains("The attribute has been saved."));
+    let saved = sqlx::query!(
+        "SELECT attributes FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.attributes.get("first name").is_none());
+}
+
+#[tokio::test]
+async fn segments_show_how_many_subscribers_they_match() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+
+    let response = app
+        .post_create_segment(&serde_json::json!({
+            "name": "French",
+            "attributes": "country=fr\nplan=pro",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/segments");
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("<p><i>The segment has been created.</i></p>"));
+    assert!(html_page.contains("country=fr, plan=pro"));
+    assert!(html_page.contains("<td>1</td>"));
+}
+
+#[tokio::test]
+async fn segments_with_malformed_conditions_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_create_segment(&serde_json::json!({
+        "name": "French",
+        "attributes": "country",
+    }))
+    .await;
+
+    let html_page = app.get_segments_html().await;
+    assert!(!html_page.contains("The segment has been created."));
+    let n_segments = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM segments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_segments, 0);
+}
+
+#[tokio::test]
+async fn issues_targeting_a_segment_only_go_to_matching_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+    app.post_create_segment(&serde_json::json!({
+        "name": "Americans",
+        "attributes": "country=us",
+    }))
+    .await;
+    let segment_id = sqlx::query!("SELECT segment_id FROM segments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .segment_id;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment_id": segment_id.to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let recipients = sqlx::query!("SELECT subscriber_email FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(recipients.len(), 1);
+    assert_eq!(recipients[0].subscriber_email, "octavia@example.com");
+}
+
+#[tokio::test]
+async fn an_empty_segment_choice_sends_to_everyone() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment_id": "",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+}
@@ -0,0 +1,162 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn set_admin_email(app: &TestApp) {
+    let response = app
+        .post_email_address(&serde_json::json!({"email": "admin@example.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/email");
+}
+
+#[tokio::test]
+async fn an_invalid_email_address_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_email_address(&serde_json::json!({"email": "not-an-email"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/email");
+
+    let html_page = app.get_email_address_html().await;
+    assert!(html_page.contains("<p><i>Please enter a valid email address.</i></p>"));
+}
+
+#[tokio::test]
+async fn sending_a_test_requires_an_email_address() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_send_test_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as html</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>Set your email address before sending a test copy.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_test_copy_is_sent_to_the_admin_only() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_email_address(&serde_json::json!({"email": "admin@example.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/email");
+    let html_page = app.get_email_address_html().await;
+    assert!(html_page.contains("<p><i>Your email address has been updated.</i></p>"));
+    assert!(html_page.contains(r#"value="admin@example.com""#));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_send_test_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as html</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>A test copy has been sent to admin@example.com.</i></p>"));
+    // The first request is the subscriber's confirmation email.
+    let requests = app.email_server.received_requests().await.unwrap();
+    let request = requests.last().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["To"], "admin@example.com");
+    assert_eq!(body["Subject"], "[Test] Newsletter title");
+
+    let n_issues = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues.count, 0);
+    let n_queued = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issues_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued.count, 0);
+}
+
+#[tokio::test]
+async fn merge_tags_are_filled_in_for_the_admin_in_a_test_copy() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    set_admin_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_send_test_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ name }}, this is for {{ email }}.",
+            "html_content": "<p>Hi {{ name }}, this is for {{ email }}.</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Hi Test Subscriber, this is for admin@example.com."));
+    assert!(text_body.contains("/subscriptions/unsubscribe?email=admin%40example.com&token="));
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Hi Test Subscriber, this is for admin@example.com.</p>"));
+}
+
+#[tokio::test]
+async fn a_test_copy_with_an_unknown_merge_tag_is_not_sent() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    set_admin_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_send_test_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ nickname }}",
+            "html_content": "<p>Hi {{ nickname }}</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_html().await;
+    assert!(html_page.contains("<p><i>Unknown merge tag {{ nickname }}.</i></p>"));
+}
@@ -0,0 +1,263 @@
+use reqwest::Url;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+struct DataLinks {
+    export: Url,
+    erase: Url,
+}
+
+/// Pulls the export and erasure links out of the last email sent.
+async fn get_data_links(app: &TestApp) -> DataLinks {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(body["TextBody"].as_str().unwrap())
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .map(|l| {
+            let mut link = Url::parse(l.as_str()).unwrap();
+            link.set_port(Some(app.port)).unwrap();
+            link
+        })
+        .collect();
+    assert_eq!(links.len(), 2);
+    DataLinks {
+        export: links[0].clone(),
+        erase: links[1].clone(),
+    }
+}
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+#[tokio::test]
+async fn a_data_request_for_an_unknown_address_sends_nothing() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_data_request("email=nobody%40example.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If nobody@example.com is on our list"));
+}
+
+#[tokio::test]
+async fn a_data_request_only_queues_the_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    let response = app.post_data_request(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(&format!("If {} is on our list", email)));
+    let queued = sqlx::query!("SELECT kind FROM subscriber_email_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.kind, "data_request");
+}
+
+#[tokio::test]
+async fn repeated_data_requests_send_a_single_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    for _ in 0..3 {
+        let response = app.post_data_request(body.clone()).await;
+        assert_eq!(response.status().as_u16(), 200);
+        app.dispatch_all_pending_subscriber_emails().await;
+    }
+}
+
+#[tokio::test]
+async fn a_subscriber_can_download_their_data_through_the_emailed_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = '{"country": "IT"}', email_format = 'plain_text'"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    let response = app.post_data_request(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.dispatch_all_pending_subscriber_emails().await;
+
+    let links = get_data_links(&app).await;
+    let response = reqwest::get(links.export).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["email"], email.as_str());
+    assert_eq!(export["subscription"]["status"], "confirmed");
+    assert_eq!(export["subscription"]["attributes"]["country"], "IT");
+    assert_eq!(export["preferences"]["email_format"], "plain_text");
+    assert_eq!(
+        export["preferences"]["lists"],
+        serde_json::json!(["Newsletter"])
+    );
+    assert!(export["deliveries"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn an_export_link_cannot_be_used_to_erase() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    app.post_data_request(body).await;
+
+    app.dispatch_all_pending_subscriber_emails().await;
+
+    let links = get_data_links(&app).await;
+    let mut forged = links.erase.clone();
+    forged.set_query(links.export.query());
+    let response = reqwest::Client::new().post(forged).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_email(&app).await, email);
+}
+
+#[tokio::test]
+async fn a_subscriber_can_erase_their_data_through_the_emailed_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let issue_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO newsletter_issues (newsletter_issues_id, title, text_content, html_content, status) VALUES ($1, 'Title', 'Text', '<p>Html</p>', 'sent')",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_deliveries (newsletter_issues_id, subscriber_email, outcome, n_attempts, delivered_at) VALUES ($1, $2, 'sent', 1, now())",
+        issue_id,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("email", &email)]).unwrap();
+    app.post_data_request(body).await;
+    app.dispatch_all_pending_subscriber_emails().await;
+    let links = get_data_links(&app).await;
+
+    // Following the link only asks for confirmation.
+    let response = reqwest::get(links.erase.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Erase my data"));
+    assert_eq!(subscriber_email(&app).await, email);
+
+    let response = reqwest::Client::new()
+        .post(links.erase)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+    let delivery = sqlx::query!("SELECT subscriber_email, outcome FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(delivery.subscriber_email, email);
+    assert_eq!(delivery.outcome, "sent");
+
+    let response = reqwest::get(links.export).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn an_expired_data_link_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    let expired_at = chrono::Utc::now() - chrono::Duration::hours(1);
+    let mut link = Url::parse(&app.link_signer.signed_url_until(
+        "/subscriptions/export",
+        &email,
+        expired_at,
+    ))
+    .unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,224 @@
+use uuid::Uuid;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    TestApp,
+};
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, $3, now(), $4)",
+        subscriber_id,
+        email,
+        name,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscribers("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_delete_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+
+    assert_is_redirect_to(&response, "/login");
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 1);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(
+        &app,
+        "le_guin@example.com",
+        "Le Guin",
+        "pending_confirmation",
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("2 subscribers"));
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("le_guin@example.com"));
+
+    let html_page = app.get_subscribers_html("?search=URSULA").await;
+    assert!(html_page.contains("1 subscribers"));
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("le_guin@example.com"));
+
+    let html_page = app.get_subscribers_html("?search=le%20guin").await;
+    assert!(html_page.contains("1 subscribers"));
+    assert!(html_page.contains("le_guin@example.com"));
+
+    // `_` is matched literally rather than as a wildcard.
+    let html_page = app.get_subscribers_html("?search=e_g").await;
+    assert!(html_page.contains("1 subscribers"));
+    assert!(html_page.contains("le_guin@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    for i in 0..51 {
+        insert_subscriber(
+            &app,
+            &format!("subscriber{}@example.com", i),
+            "Subscriber",
+            "confirmed",
+        )
+        .await;
+    }
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("51 subscribers"));
+    assert!(html_page.contains("Page 1 of 2"));
+    assert_eq!(html_page.matches("<tr>").count(), 51);
+
+    let html_page = app.get_subscribers_html("?page=2").await;
+    assert!(html_page.contains("Page 2 of 2"));
+    assert_eq!(html_page.matches("<tr>").count(), 2);
+}
+
+#[tokio::test]
+async fn a_page_far_past_the_end_is_empty() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscribers(&format!("?page={}", i64::MAX)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!("Page {} of 1", i64::MAX)));
+    assert_eq!(html_page.matches("<tr>").count(), 1);
+}
+
+#[tokio::test]
+async fn an_admin_can_confirm_a_pending_subscriber() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been confirmed.</i></p>"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_cannot_be_confirmed_by_an_admin() {
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "unsubscribed").await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(!html_page.contains(&format!("/admin/subscribers/{}/confirm", subscriber_id)));
+
+    let response = app.post_subscriber_action(&subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page
+        .contains("<p><i>Only subscribers waiting for confirmation can be confirmed.</i></p>"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn an_admin_can_unsubscribe_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_subscriber_action(&subscriber_id, "unsubscribe")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been unsubscribed.</i></p>"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn an_admin_can_delete_a_subscriber_and_their_tokens() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(&subscriber_id, "delete").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    assert!(html_page.contains("0 subscribers"));
+    let n_tokens = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn acting_on_an_unknown_subscriber_is_reported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_action(&Uuid::new_v4(), "delete").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber could not be found.</i></p>"));
+}
@@ -3,6 +3,8 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
+use zero2prod::routes::delete_stale_pending_subscribers;
+
 use crate::helpers::spawn_app;
 
 #[tokio::test]
@@ -12,7 +14,7 @@ async fn confirmations_without_token_are_rejected_with_a_400() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/subscriptions/confirm", &app.address))
+        .get(format!("{}/subscriptions/confirm", &app.address))
         .send()
         .await
         .unwrap();
@@ -32,8 +34,9 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
         .mount(&app.email_server)
         .await;
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_subscriber_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
     let response = reqwest::get(confirmation_links.html).await.unwrap();
     assert_eq!(response.status().as_u16(), 200);
 }
@@ -50,8 +53,9 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .mount(&app.email_server)
         .await;
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_subscriber_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
     let _ = reqwest::get(confirmation_links.html)
         .await
         .unwrap()
@@ -69,3 +73,98 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "bai jin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn a_confirmation_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+
+    let body = "name=bai%20jin&email=baij930312@163.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_subscriber_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_confirmation_link_offers_to_resend_it() {
+    let app = spawn_app().await;
+
+    let body = "name=bai%20jin&email=baij930312@163.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_subscriber_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - INTERVAL '30 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has expired."));
+    assert!(html_page.contains(r#"value="baij930312@163.com""#));
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn stale_pending_subscribers_are_purged() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=stale&email=stale@163.com".into())
+        .await;
+    app.dispatch_all_pending_subscriber_emails().await;
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = now() - INTERVAL '30 days' WHERE email = 'stale@163.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - INTERVAL '30 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_subscriptions("name=fresh&email=fresh@163.com".into())
+        .await;
+
+    delete_stale_pending_subscribers(
+        &app.db_pool,
+        app.configuration.application.subscription_token_lifetime(),
+    )
+    .await
+    .unwrap();
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "fresh@163.com");
+}